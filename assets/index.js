@@ -1,22 +1,283 @@
 let latestPosition = null;
+let latestHighlight = [];
 
-function updateDocument(newContent) {
+// Scrolling caused by the editor is not reported back to it
+let suppressScrollReportsUntil = 0;
+
+// Applies a list of blocks from a `file_changed` event. Blocks without HTML are
+// already on the page and are looked up by their data-sourcepos; the rest are
+// new or changed. Returns false when the page is out of sync with the patch.
+function patchDocument(blocks) {
   const main = document.querySelector('main');
   if (!main) {
+    return true;
+  }
+
+  const existing = new Map();
+  for (const element of main.children) {
+    existing.set(element.getAttribute('data-sourcepos'), element);
+  }
+
+  const elements = [];
+  for (const block of blocks) {
+    if (block.html === null) {
+      const element = existing.get(block.sourcepos);
+      if (!element) {
+        return false;
+      }
+      elements.push(element);
+    } else {
+      const template = document.createElement('template');
+      template.innerHTML = block.html;
+      elements.push(template.content.firstElementChild);
+    }
+  }
+
+  // Only touch the DOM where the order differs, so unchanged blocks keep their
+  // state (loaded images, scroll positions of code blocks, ...)
+  let current = main.firstElementChild;
+  for (const element of elements) {
+    if (element === current) {
+      current = current.nextElementSibling;
+    } else {
+      main.insertBefore(element, current);
+    }
+  }
+  while (current) {
+    const next = current.nextElementSibling;
+    current.remove();
+    current = next;
+  }
+  return true;
+}
+
+const scriptLoaders = new Map();
+
+// Loads a bundled script from /assets/ once and resolves with the global it
+// defines.
+function loadScript(name, globalName) {
+  if (!scriptLoaders.has(name)) {
+    scriptLoaders.set(name, new Promise((resolve, reject) => {
+      const script = document.createElement('script');
+      script.src = `/assets/${name}`;
+      script.onload = () => {
+        if (window[globalName]) {
+          resolve(window[globalName]);
+        } else {
+          reject(new Error(`${name} did not define window.${globalName}`));
+        }
+      };
+      script.onerror = () => reject(new Error(`Failed to load /assets/${name}`));
+      document.head.appendChild(script);
+    }));
+  }
+  return scriptLoaders.get(name);
+}
+
+let mermaidInitialized = false;
+
+async function loadMermaid() {
+  const mermaid = await loadScript('mermaid.min.js', 'mermaid');
+  if (!mermaidInitialized) {
+    const dark = window.matchMedia('(prefers-color-scheme: dark)').matches;
+    mermaid.initialize({ startOnLoad: false, theme: dark ? 'dark' : 'default' });
+    mermaidInitialized = true;
+  }
+  return mermaid;
+}
+
+// Typesets $...$ and $$...$$ math that has not been typeset yet. KaTeX emits
+// MathML, which browsers render without extra fonts or styles.
+async function renderMath() {
+  const selector = '[data-math-style]:not([data-math-rendered])';
+  if (!document.querySelector(selector)) {
+    return;
+  }
+
+  let katex;
+  try {
+    katex = await loadScript('katex.min.js', 'katex');
+  } catch (error) {
+    console.error(error);
+    return;
+  }
+
+  for (const element of document.querySelectorAll(selector)) {
+    katex.render(element.textContent, element, {
+      displayMode: element.dataset.mathStyle === 'display',
+      output: 'mathml',
+      throwOnError: false,
+    });
+    element.setAttribute('data-math-rendered', '');
+  }
+}
+
+// Renders ```mermaid code blocks that have not been rendered yet. The <pre>
+// keeps its data-sourcepos so that patching still finds the block.
+async function renderDiagrams() {
+  const selector = 'pre > code.language-mermaid';
+  if (!document.querySelector(selector)) {
+    return;
+  }
+
+  let mermaid;
+  try {
+    mermaid = await loadMermaid();
+  } catch (error) {
+    console.error(error);
     return;
   }
-  main.innerHTML = newContent;
+
+  const nodes = [];
+  for (const code of document.querySelectorAll(selector)) {
+    const diagram = document.createElement('div');
+    diagram.className = 'mermaid';
+    diagram.textContent = code.textContent;
+
+    const pre = code.parentElement;
+    pre.classList.add('mermaid-diagram');
+    pre.replaceChildren(diagram);
+    nodes.push(diagram);
+  }
+  // mermaid 9 reports diagrams that fail to parse on the console and renders
+  // the rest
+  mermaid.init(undefined, nodes);
+}
+
+// Rebuilds the table of contents from the outline of a `file_changed` event.
+function updateOutline(outline) {
+  const toc = document.querySelector('nav.toc');
+  if (!toc) {
+    return;
+  }
+
+  const items = outline.map((heading) => {
+    const link = document.createElement('a');
+    if (heading.anchor !== null) {
+      link.href = `#${heading.anchor}`;
+    }
+    link.dataset.sourcepos = heading.sourcepos;
+    link.textContent = heading.text;
+
+    const item = document.createElement('li');
+    item.className = `toc-level-${heading.level}`;
+    item.appendChild(link);
+    return item;
+  });
+  toc.querySelector('ul').replaceChildren(...items);
+  toc.hidden = outline.length === 0;
+  highlightCurrentSection();
+}
+
+// Marks the entry of the last heading scrolled past the top of the window.
+function highlightCurrentSection() {
+  const links = document.querySelectorAll('nav.toc a[data-sourcepos]');
+  let current = null;
+  for (const link of links) {
+    const heading = document.querySelector(`main [data-sourcepos="${link.dataset.sourcepos}"]`);
+    if (!heading) {
+      continue;
+    }
+    if (current && heading.getBoundingClientRect().top > window.innerHeight / 4) {
+      break;
+    }
+    current = link;
+  }
+
+  for (const link of links) {
+    link.classList.toggle('active', link === current);
+  }
 }
 
 function scrollToNewPosition(sourcepos) {
-  const element = document.querySelector(`[data-sourcepos="${sourcepos}"]`);
+  const element = document.querySelector(`main [data-sourcepos="${sourcepos}"]`);
   if (!element) {
     console.warn(`Element with data-sourcepos="${sourcepos}" not found`);
     return;
   }
+  suppressScrollReportsUntil = Date.now() + 1000;
   element.scrollIntoView({ behavior: 'smooth', block: 'center' });
 }
 
+// Marks the blocks at the editor's cursor or selection, replacing the previous
+// highlight.
+function highlightActiveBlocks(sourcepos) {
+  for (const element of document.querySelectorAll('main .active-block')) {
+    element.classList.remove('active-block');
+  }
+  for (const range of sourcepos) {
+    document.querySelector(`main [data-sourcepos="${range}"]`)?.classList.add('active-block');
+  }
+}
+
+// Returns the data-sourcepos of the first block that is still visible at the
+// top of the window.
+function topmostVisibleSourcepos() {
+  const main = document.querySelector('main');
+  if (!main) {
+    return null;
+  }
+  for (const element of main.children) {
+    if (element.getBoundingClientRect().bottom > 0) {
+      return element.getAttribute('data-sourcepos');
+    }
+  }
+  return null;
+}
+
+// Reports the topmost visible block to the server at most every 200ms, so that
+// editors following the preview can move their cursor.
+function watchScrollPosition(documentId) {
+  let lastReported = null;
+  let timer = null;
+
+  const report = () => {
+    timer = null;
+    if (Date.now() < suppressScrollReportsUntil) {
+      return;
+    }
+    const sourcepos = topmostVisibleSourcepos();
+    if (!sourcepos || sourcepos === lastReported) {
+      return;
+    }
+    lastReported = sourcepos;
+    fetch(`/api/document/${documentId}/scroll`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ sourcepos }),
+    }).catch((error) => console.error('Failed to report scroll position:', error));
+  };
+
+  window.addEventListener('scroll', () => {
+    if (timer === null) {
+      timer = setTimeout(report, 200);
+    }
+  }, { passive: true });
+}
+
+// Messages shown in the status banner, by what they are about
+const statusMessages = new Map();
+
+// Shows `message` about `topic` in the status banner, or removes it when it
+// is null. The banner is hidden while there is nothing to show.
+function setStatus(topic, message) {
+  if (message) {
+    statusMessages.set(topic, message);
+  } else {
+    statusMessages.delete(topic);
+  }
+
+  let banner = document.querySelector('.status-banner');
+  if (!banner) {
+    banner = document.createElement('div');
+    banner.className = 'status-banner';
+    banner.setAttribute('role', 'status');
+    document.body.appendChild(banner);
+  }
+  banner.textContent = [...statusMessages.values()].join(' \u00b7 ');
+  banner.hidden = statusMessages.size === 0;
+}
+
 (function() {
   if (!window.location.pathname.startsWith('/document/')) {
     return;
@@ -28,24 +289,79 @@ function scrollToNewPosition(sourcepos) {
     return;
   }
 
+  renderDiagrams();
+  renderMath();
+
+  // Headings are looked up by sourcepos, which also works without header ids
+  document.querySelector('nav.toc')?.addEventListener('click', (event) => {
+    const link = event.target.closest('a[data-sourcepos]');
+    const heading = link && document.querySelector(`main [data-sourcepos="${link.dataset.sourcepos}"]`);
+    if (!heading) {
+      return;
+    }
+    event.preventDefault();
+    heading.scrollIntoView({ behavior: 'smooth', block: 'start' });
+    if (link.hash) {
+      history.replaceState(null, '', link.hash);
+    }
+  });
+
+  let highlightScheduled = false;
+  window.addEventListener('scroll', () => {
+    if (highlightScheduled) {
+      return;
+    }
+    highlightScheduled = true;
+    requestAnimationFrame(() => {
+      highlightScheduled = false;
+      highlightCurrentSection();
+    });
+  }, { passive: true });
+  highlightCurrentSection();
+  watchScrollPosition(documentId);
+
   const eventSource = new EventSource(`/document/${documentId}/updates`);
   eventSource.addEventListener('position', (event) => {
     const data = JSON.parse(event.data);
     scrollToNewPosition(data.sourcepos);
+    highlightActiveBlocks(data.highlight);
     latestPosition = data.sourcepos;
+    latestHighlight = data.highlight;
   });
   eventSource.addEventListener('file_changed', (event) => {
     const data = JSON.parse(event.data);
-    updateDocument(data.html);
+    if (!patchDocument(data.blocks)) {
+      window.location.reload();
+      return;
+    }
+    updateOutline(data.outline);
+    renderDiagrams();
+    renderMath();
     if (latestPosition) {
       scrollToNewPosition(latestPosition);
     }
+    highlightActiveBlocks(latestHighlight);
+    setStatus('file', null);
+  });
+  eventSource.addEventListener('file_renamed', (event) => {
+    const data = JSON.parse(event.data);
+    document.title = data.title;
+    setStatus('file', null);
+  });
+  eventSource.addEventListener('file_deleted', (event) => {
+    const data = JSON.parse(event.data);
+    setStatus('file', `${data.filepath} was deleted`);
   });
+  // EventSource reconnects on its own, and the server then sends whatever was
+  // missed in the meantime
   eventSource.addEventListener('error', (event) => {
     console.error('SSE connection error:', event);
+    const reconnecting = eventSource.readyState === EventSource.CONNECTING;
+    setStatus('connection', reconnecting ? 'Disconnected, reconnecting\u2026' : 'Disconnected');
   });
   eventSource.addEventListener('open', (event) => {
     console.log('SSE connection opened');
+    setStatus('connection', null);
   });
   window.addEventListener('beforeunload', () => {
     eventSource.close();
@@ -0,0 +1,16 @@
+#!/bin/sh
+# Downloads the third-party browser libraries that are embedded into the
+# binary and served from /assets/. Run from the repository root.
+set -eu
+
+MERMAID_VERSION=9.1.5
+KATEX_VERSION=0.16.4
+
+curl -fsSL -o assets/vendor/mermaid.min.js \
+  "https://cdn.jsdelivr.net/npm/mermaid@${MERMAID_VERSION}/dist/mermaid.min.js"
+curl -fsSL -o assets/vendor/mermaid.LICENSE \
+  "https://raw.githubusercontent.com/mermaid-js/mermaid/v${MERMAID_VERSION}/LICENSE"
+curl -fsSL -o assets/vendor/katex.min.js \
+  "https://cdn.jsdelivr.net/npm/katex@${KATEX_VERSION}/dist/katex.min.js"
+curl -fsSL -o assets/vendor/katex.LICENSE \
+  "https://raw.githubusercontent.com/KaTeX/KaTeX/v${KATEX_VERSION}/LICENSE"
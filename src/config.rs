@@ -0,0 +1,313 @@
+use facet::Facet;
+use std::fmt;
+use std::path::Path;
+
+/// Name of the configuration file looked up in the working directory.
+pub const CONFIG_FILENAME: &str = "livemarkdown.toml";
+
+/// Markdown syntax extensions that can be switched on and off.
+///
+/// Plain CommonMark is always parsed; each flag adds one extension on top.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExtensionSet {
+    pub strikethrough: bool,
+    pub tagfilter: bool,
+    pub table: bool,
+    pub autolink: bool,
+    pub tasklist: bool,
+    pub superscript: bool,
+    pub header_ids: bool,
+    pub footnotes: bool,
+    pub description_lists: bool,
+    pub front_matter: bool,
+    pub math: bool,
+    pub wikilinks: bool,
+    pub alerts: bool,
+}
+
+/// Names accepted by `enable`/`disable`, in the order they are documented.
+pub const EXTENSION_NAMES: &[&str] = &[
+    "strikethrough",
+    "tagfilter",
+    "table",
+    "autolink",
+    "tasklist",
+    "superscript",
+    "header_ids",
+    "footnotes",
+    "description_lists",
+    "front_matter",
+    "math",
+    "wikilinks",
+    "alerts",
+];
+
+/// Names accepted by `preset`.
+pub const PRESET_NAMES: &[&str] = &["default", "gfm", "commonmark"];
+
+impl ExtensionSet {
+    /// Plain CommonMark without any extension.
+    pub fn commonmark() -> Self {
+        ExtensionSet {
+            strikethrough: false,
+            tagfilter: false,
+            table: false,
+            autolink: false,
+            tasklist: false,
+            superscript: false,
+            header_ids: false,
+            footnotes: false,
+            description_lists: false,
+            front_matter: false,
+            math: false,
+            wikilinks: false,
+            alerts: false,
+        }
+    }
+
+    /// The extensions of the GitHub Flavored Markdown spec.
+    pub fn gfm() -> Self {
+        ExtensionSet {
+            strikethrough: true,
+            tagfilter: true,
+            table: true,
+            autolink: true,
+            tasklist: true,
+            ..Self::commonmark()
+        }
+    }
+
+    pub fn preset(name: &str) -> Result<Self, ConfigError> {
+        match name {
+            "default" => Ok(Self::default()),
+            "gfm" => Ok(Self::gfm()),
+            "commonmark" => Ok(Self::commonmark()),
+            _ => Err(ConfigError::UnknownPreset(name.to_string())),
+        }
+    }
+
+    /// Turns the extension called `name` on or off.
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), ConfigError> {
+        let flag = match name {
+            "strikethrough" => &mut self.strikethrough,
+            "tagfilter" => &mut self.tagfilter,
+            "table" => &mut self.table,
+            "autolink" => &mut self.autolink,
+            "tasklist" => &mut self.tasklist,
+            "superscript" => &mut self.superscript,
+            "header_ids" => &mut self.header_ids,
+            "footnotes" => &mut self.footnotes,
+            "description_lists" => &mut self.description_lists,
+            "front_matter" => &mut self.front_matter,
+            "math" => &mut self.math,
+            "wikilinks" => &mut self.wikilinks,
+            "alerts" => &mut self.alerts,
+            _ => return Err(ConfigError::UnknownExtension(name.to_string())),
+        };
+        *flag = enabled;
+        Ok(())
+    }
+}
+
+impl Default for ExtensionSet {
+    /// GFM plus the extras livemarkdown has always rendered.
+    fn default() -> Self {
+        ExtensionSet {
+            superscript: true,
+            header_ids: true,
+            footnotes: true,
+            description_lists: true,
+            front_matter: true,
+            math: true,
+            ..Self::gfm()
+        }
+    }
+}
+
+/// A change to an [`ExtensionSet`], as written in `livemarkdown.toml`, on the
+/// command line or in `POST /api/document`: an optional preset to start from,
+/// then extensions to turn on and off.
+#[derive(Facet, Clone, Debug, Default, PartialEq)]
+pub struct ExtensionSettings {
+    #[facet(default)]
+    pub preset: Option<String>,
+    #[facet(default)]
+    pub enable: Vec<String>,
+    #[facet(default)]
+    pub disable: Vec<String>,
+}
+
+impl ExtensionSettings {
+    /// Applies the settings on top of `base`, which is kept unless a preset is
+    /// given.
+    pub fn resolve(&self, base: &ExtensionSet) -> Result<ExtensionSet, ConfigError> {
+        let mut extensions = match &self.preset {
+            Some(preset) => ExtensionSet::preset(preset)?,
+            None => *base,
+        };
+        for name in &self.enable {
+            extensions.set(name, true)?;
+        }
+        for name in &self.disable {
+            extensions.set(name, false)?;
+        }
+        Ok(extensions)
+    }
+
+    /// Parses the `[markdown]` table of a `livemarkdown.toml` file.
+    pub fn from_toml(content: &str) -> Result<Self, ConfigError> {
+        let table: toml::Table = content
+            .parse()
+            .map_err(|e: toml::de::Error| ConfigError::Invalid(e.message().to_string()))?;
+
+        let Some(markdown) = table.get("markdown") else {
+            return Ok(Self::default());
+        };
+        let markdown = markdown
+            .as_table()
+            .ok_or_else(|| ConfigError::Invalid("[markdown] must be a table".to_string()))?;
+
+        let mut settings = Self::default();
+        for (key, value) in markdown {
+            match key.as_str() {
+                "preset" => {
+                    let preset = value.as_str().ok_or_else(|| {
+                        ConfigError::Invalid("markdown.preset must be a string".to_string())
+                    })?;
+                    settings.preset = Some(preset.to_string());
+                }
+                "enable" => settings.enable = string_list(key, value)?,
+                "disable" => settings.disable = string_list(key, value)?,
+                _ => {
+                    return Err(ConfigError::Invalid(format!(
+                        "unknown key markdown.{}",
+                        key
+                    )))
+                }
+            }
+        }
+
+        // Catch typos when the file is loaded rather than on first render
+        settings.resolve(&ExtensionSet::default())?;
+        Ok(settings)
+    }
+
+    /// Reads `path`, returning default settings if the file does not exist.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Self::from_toml(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(ConfigError::Invalid(format!(
+                "cannot read {}: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+}
+
+fn string_list(key: &str, value: &toml::Value) -> Result<Vec<String>, ConfigError> {
+    let invalid = || ConfigError::Invalid(format!("markdown.{} must be a list of strings", key));
+    value
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|item| item.as_str().map(str::to_string).ok_or_else(invalid))
+        .collect()
+}
+
+/// Why an extension configuration was rejected.
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    UnknownPreset(String),
+    UnknownExtension(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnknownPreset(name) => write!(
+                f,
+                "unknown preset \"{}\" (expected one of: {})",
+                name,
+                PRESET_NAMES.join(", ")
+            ),
+            ConfigError::UnknownExtension(name) => write!(
+                f,
+                "unknown extension \"{}\" (expected one of: {})",
+                name,
+                EXTENSION_NAMES.join(", ")
+            ),
+            ConfigError::Invalid(message) => write!(f, "invalid configuration: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_apply_preset_then_toggles() {
+        let settings = ExtensionSettings {
+            preset: Some("gfm".to_string()),
+            enable: vec!["wikilinks".to_string(), "alerts".to_string()],
+            disable: vec!["tagfilter".to_string()],
+        };
+        let extensions = settings.resolve(&ExtensionSet::default()).unwrap();
+
+        assert!(extensions.table);
+        assert!(extensions.wikilinks);
+        assert!(extensions.alerts);
+        assert!(!extensions.tagfilter);
+        assert!(!extensions.footnotes);
+    }
+
+    #[test]
+    fn test_settings_without_preset_keep_base() {
+        let settings = ExtensionSettings {
+            disable: vec!["math".to_string()],
+            ..Default::default()
+        };
+        let extensions = settings.resolve(&ExtensionSet::gfm()).unwrap();
+
+        assert_eq!(extensions, ExtensionSet::gfm());
+    }
+
+    #[test]
+    fn test_unknown_names_are_rejected() {
+        let settings = ExtensionSettings {
+            enable: vec!["tables".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.resolve(&ExtensionSet::default()),
+            Err(ConfigError::UnknownExtension("tables".to_string()))
+        );
+        assert_eq!(
+            ExtensionSet::preset("github"),
+            Err(ConfigError::UnknownPreset("github".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let settings = ExtensionSettings::from_toml(
+            "[markdown]\npreset = \"commonmark\"\nenable = [\"footnotes\"]\n",
+        )
+        .unwrap();
+        assert_eq!(settings.preset.as_deref(), Some("commonmark"));
+        assert_eq!(settings.enable, vec!["footnotes"]);
+
+        assert_eq!(
+            ExtensionSettings::from_toml("").unwrap(),
+            ExtensionSettings::default()
+        );
+        assert!(ExtensionSettings::from_toml("[markdown]\nenable = \"math\"\n").is_err());
+        assert!(ExtensionSettings::from_toml("[markdown]\npreset = \"github\"\n").is_err());
+        assert!(ExtensionSettings::from_toml("[markdown]\ncolor = true\n").is_err());
+    }
+}
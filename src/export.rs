@@ -0,0 +1,265 @@
+use crate::config::ExtensionSet;
+use crate::{front_matter, html_template, markdown, utils};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Renders a markdown file, or every markdown file under a directory, into
+/// standalone HTML files in `output_dir`.
+///
+/// The directory layout is preserved, links between exported documents point
+/// at the generated `.html` files and local images are copied next to them.
+/// If any document contains math, KaTeX is written to the root of
+/// `output_dir`. Returns the paths of the written HTML files.
+pub fn export(
+    input: &Path,
+    output_dir: &Path,
+    extensions: &ExtensionSet,
+) -> io::Result<Vec<PathBuf>> {
+    let input = PathBuf::from(utils::to_absolute_path(&input.to_string_lossy()));
+
+    let (root, documents) = if input.is_dir() {
+        (input.clone(), utils::find_markdown_files(&input))
+    } else if input.is_file() {
+        let root = input.parent().unwrap_or(Path::new("/")).to_path_buf();
+        (root, vec![input.clone()])
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("File not found: {}", input.display()),
+        ));
+    };
+
+    std::fs::create_dir_all(output_dir)?;
+    let output_dir = PathBuf::from(utils::to_absolute_path(&output_dir.to_string_lossy()));
+
+    let exported: HashSet<PathBuf> = documents.iter().cloned().collect();
+    let mut written = Vec::new();
+
+    for document in &documents {
+        let content = std::fs::read_to_string(document)?;
+        let resolver = ExportLinkResolver {
+            root: &root,
+            output_dir: &output_dir,
+            source: document,
+            exported: &exported,
+        };
+        let html = markdown::render_document_to_html(&content, document, &resolver, extensions);
+
+        let title = extensions
+            .front_matter
+            .then(|| front_matter::parse(&content))
+            .flatten()
+            .and_then(|front_matter| front_matter.title)
+            .unwrap_or_else(|| {
+                document
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .unwrap_or("Markdown Document")
+                    .to_string()
+            });
+
+        let output_path = output_path(&root, &output_dir, document);
+        let katex_path = output_dir.join(KATEX_FILENAME);
+        let katex_url =
+            utils::relative_url(output_path.parent().unwrap_or(&output_dir), &katex_path);
+        let page = html_template::wrap_in_static_html_template(&html, Some(&title), &katex_url);
+
+        if html_template::contains_math(&html)
+            && !katex_path.exists()
+            && let Some((_, script)) = html_template::vendor_asset(KATEX_FILENAME)
+        {
+            std::fs::write(&katex_path, script)?;
+        }
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&output_path, page)?;
+        written.push(output_path);
+    }
+
+    Ok(written)
+}
+
+const KATEX_FILENAME: &str = "katex.min.js";
+
+fn output_path(root: &Path, output_dir: &Path, document: &Path) -> PathBuf {
+    let relative_path = document.strip_prefix(root).unwrap_or(document);
+    output_dir.join(relative_path).with_extension("html")
+}
+
+/// Points links at the exported HTML files and copies images into the output
+/// directory.
+struct ExportLinkResolver<'a> {
+    root: &'a Path,
+    output_dir: &'a Path,
+    source: &'a Path,
+    exported: &'a HashSet<PathBuf>,
+}
+
+impl ExportLinkResolver<'_> {
+    fn url_from_source(&self, target: &Path) -> String {
+        let source_dir = self.source.parent().unwrap_or(self.root);
+        utils::relative_url(source_dir, target)
+    }
+}
+
+impl markdown::LinkResolver for ExportLinkResolver<'_> {
+    fn document_url(&self, path: &Path) -> Option<String> {
+        if !self.exported.contains(path) {
+            return None;
+        }
+
+        Some(self.url_from_source(&path.with_extension("html")))
+    }
+
+    fn asset_url(&self, path: &Path) -> Option<String> {
+        let relative_path = path.strip_prefix(self.root).ok()?;
+        if !path.is_file() {
+            return None;
+        }
+
+        let destination = self.output_dir.join(relative_path);
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent).ok()?;
+        }
+        std::fs::copy(path, &destination).ok()?;
+
+        Some(self.url_from_source(path))
+    }
+
+    fn include(&self, path: &Path) -> Option<String> {
+        if !utils::is_includable(self.root, path) {
+            return None;
+        }
+        std::fs::read_to_string(path).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let tmp_dir = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        let dir = PathBuf::from(format!("{}/{}_{}", tmp_dir, name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_export_directory() {
+        let dir = temp_dir("test_export_directory");
+        let input = dir.join("docs");
+        let output = dir.join("out");
+        fs::create_dir_all(input.join("guide/img")).unwrap();
+        fs::write(
+            input.join("index.md"),
+            "# Index\n\n[Setup](guide/setup.md#install)",
+        )
+        .unwrap();
+        fs::write(
+            input.join("guide/setup.md"),
+            "# Setup\n\n[Home](../index.md)\n\n![shot](img/shot.png)",
+        )
+        .unwrap();
+        fs::write(input.join("guide/img/shot.png"), b"\x89PNG\r\n").unwrap();
+
+        let written = export(&input, &output, &ExtensionSet::default()).unwrap();
+        assert_eq!(written.len(), 2);
+
+        let index = fs::read_to_string(output.join("index.html")).unwrap();
+        assert!(index.contains("<title>index</title>"));
+        assert!(index.contains(r#"href="guide/setup.html#install""#));
+        assert!(index.contains("<style>"));
+        assert!(!index.contains("EventSource"));
+
+        let setup = fs::read_to_string(output.join("guide/setup.html")).unwrap();
+        assert!(setup.contains(r#"href="../index.html""#));
+        assert!(setup.contains(r#"src="img/shot.png""#));
+        assert!(output.join("guide/img/shot.png").is_file());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_export_single_file_leaves_other_links() {
+        let dir = temp_dir("test_export_single_file");
+        fs::write(dir.join("a.md"), "[B](b.md)").unwrap();
+        fs::write(dir.join("b.md"), "# B").unwrap();
+
+        let written = export(
+            &dir.join("a.md"),
+            &dir.join("out"),
+            &ExtensionSet::default(),
+        )
+        .unwrap();
+        assert_eq!(written.len(), 1);
+        assert!(written[0].ends_with("out/a.html"));
+
+        let a = fs::read_to_string(dir.join("out/a.html")).unwrap();
+        assert!(a.contains(r#"href="b.md""#));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_export_only_includes_files_inside_root() {
+        let dir = temp_dir("test_export_includes");
+        let input = dir.join("docs");
+        fs::create_dir_all(&input).unwrap();
+        fs::write(input.join("snippet.md"), "Shared text").unwrap();
+        fs::write(input.join(".env"), "TOKEN=hidden-token").unwrap();
+        fs::write(dir.join("secret.txt"), "outside-root").unwrap();
+        fs::write(
+            input.join("guide.md"),
+            "{{#include snippet.md}}\n\n{{#include .env}}\n\n{{#include ../secret.txt}}\n",
+        )
+        .unwrap();
+
+        export(&input, &dir.join("out"), &ExtensionSet::default()).unwrap();
+
+        let guide = fs::read_to_string(dir.join("out/guide.html")).unwrap();
+        assert!(guide.contains("<p>Shared text</p>"));
+        assert!(!guide.contains("hidden-token"));
+        assert!(!guide.contains("outside-root"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_export_bundles_katex_for_math() {
+        let dir = temp_dir("test_export_math");
+        let input = dir.join("docs");
+        fs::create_dir_all(input.join("notes")).unwrap();
+        fs::write(input.join("plain.md"), "# Plain").unwrap();
+        fs::write(input.join("notes/euler.md"), "$e^{i\\pi} + 1 = 0$").unwrap();
+
+        export(&input, &dir.join("out"), &ExtensionSet::default()).unwrap();
+
+        let euler = fs::read_to_string(dir.join("out/notes/euler.html")).unwrap();
+        assert!(euler.contains(r#"<script src="../katex.min.js"></script>"#));
+        assert!(dir.join("out/katex.min.js").is_file());
+
+        let plain = fs::read_to_string(dir.join("out/plain.html")).unwrap();
+        assert!(!plain.contains("katex"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_export_missing_input() {
+        let dir = temp_dir("test_export_missing_input");
+
+        let result = export(
+            &dir.join("missing.md"),
+            &dir.join("out"),
+            &ExtensionSet::default(),
+        );
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,157 @@
+use facet::Facet;
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// Metadata from a document's YAML (`---`) or TOML (`+++`) front matter.
+#[derive(Facet, Clone, Debug, Default, PartialEq)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub date: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// Returns the delimiter of the front matter block `content` starts with.
+pub fn delimiter(content: &str) -> Option<&'static str> {
+    let first_line = content.lines().next()?.trim_end();
+    ["---", "+++"]
+        .into_iter()
+        .find(|&delimiter| first_line == delimiter)
+}
+
+/// Parses the front matter at the start of `content`.
+///
+/// Returns `None` if there is no front matter block or it cannot be parsed.
+pub fn parse(content: &str) -> Option<FrontMatter> {
+    let delimiter = delimiter(content)?;
+
+    let mut lines = content.lines().skip(1);
+    let mut block = String::new();
+    loop {
+        let line = lines.next()?;
+        if line.trim_end() == delimiter {
+            break;
+        }
+        block.push_str(line);
+        block.push('\n');
+    }
+
+    if delimiter == "+++" {
+        parse_toml(&block)
+    } else {
+        parse_yaml(&block)
+    }
+}
+
+fn parse_yaml(block: &str) -> Option<FrontMatter> {
+    let documents = YamlLoader::load_from_str(block).ok()?;
+    let document = documents.first()?;
+    // Only a mapping of fields is front matter
+    document.as_hash()?;
+
+    let scalar = |key: &str| yaml_scalar(&document[key]);
+    let tags = match &document["tags"] {
+        Yaml::Array(items) => items.iter().filter_map(yaml_scalar).collect(),
+        value => yaml_scalar(value)
+            .map(|tags| split_tags(&tags))
+            .unwrap_or_default(),
+    };
+
+    Some(FrontMatter {
+        title: scalar("title"),
+        author: scalar("author"),
+        date: scalar("date"),
+        tags,
+    })
+}
+
+fn yaml_scalar(value: &Yaml) -> Option<String> {
+    match value {
+        Yaml::String(value) | Yaml::Real(value) => Some(value.clone()),
+        Yaml::Integer(value) => Some(value.to_string()),
+        Yaml::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn parse_toml(block: &str) -> Option<FrontMatter> {
+    let table: toml::Table = block.parse().ok()?;
+
+    let scalar = |key: &str| table.get(key).and_then(toml_scalar);
+    let tags = match table.get("tags") {
+        Some(toml::Value::Array(items)) => items.iter().filter_map(toml_scalar).collect(),
+        Some(value) => toml_scalar(value)
+            .map(|tags| split_tags(&tags))
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    Some(FrontMatter {
+        title: scalar("title"),
+        author: scalar("author"),
+        date: scalar("date"),
+        tags,
+    })
+}
+
+fn toml_scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        toml::Value::Datetime(value) => Some(value.to_string()),
+        toml::Value::Array(_) | toml::Value::Table(_) => None,
+    }
+}
+
+/// Splits `tags: a, b` style lists.
+fn split_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_yaml() {
+        let content = "---\ntitle: Design notes\nauthor: Ada\ndate: 2024-05-01\n\
+                       tags: [design, rfc]\n---\n\n# Heading\n";
+        assert_eq!(
+            parse(content),
+            Some(FrontMatter {
+                title: Some("Design notes".to_string()),
+                author: Some("Ada".to_string()),
+                date: Some("2024-05-01".to_string()),
+                tags: vec!["design".to_string(), "rfc".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let content = "+++\ntitle = \"Release\"\ndate = 2024-05-01\ntags = \"ops, release\"\n+++\n";
+        assert_eq!(
+            parse(content),
+            Some(FrontMatter {
+                title: Some("Release".to_string()),
+                author: None,
+                date: Some("2024-05-01".to_string()),
+                tags: vec!["ops".to_string(), "release".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_without_front_matter() {
+        assert_eq!(parse("# Heading\n---\n"), None);
+        assert_eq!(parse("---\ntitle: Unterminated\n"), None);
+        assert_eq!(parse("---\n- a list\n---\n"), None);
+        assert_eq!(delimiter("+++\n"), Some("+++"));
+        assert_eq!(delimiter("# Heading"), None);
+    }
+}
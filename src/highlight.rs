@@ -0,0 +1,139 @@
+use comrak::adapters::SyntaxHighlighterAdapter;
+use comrak::html;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::sync::OnceLock;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+const LIGHT_THEME: &str = "InspiredGitHub";
+const DARK_THEME: &str = "base16-ocean.dark";
+
+/// Highlights fenced code blocks with syntect.
+///
+/// Tokens are marked up with `hl-` prefixed classes rather than inline colors,
+/// so that [`stylesheet`] can switch themes with the page's color scheme. The
+/// `<pre>` and `<code>` tags keep the attributes comrak provides, including
+/// `data-sourcepos`.
+pub struct SyntaxHighlighter;
+
+impl SyntaxHighlighterAdapter for SyntaxHighlighter {
+    fn write_highlighted(
+        &self,
+        output: &mut dyn Write,
+        lang: Option<&str>,
+        code: &str,
+    ) -> io::Result<()> {
+        let syntax_set = syntax_set();
+        let Some(syntax) = lang
+            .filter(|lang| !lang.is_empty())
+            .and_then(|lang| syntax_set.find_syntax_by_token(lang))
+        else {
+            // Unknown languages (and diagrams) are left as plain text
+            return html::escape(output, code.as_bytes());
+        };
+
+        let mut generator =
+            ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set, CLASS_STYLE);
+        for line in LinesWithEndings::from(code) {
+            if generator
+                .parse_html_for_line_which_includes_newline(line)
+                .is_err()
+            {
+                return html::escape(output, code.as_bytes());
+            }
+        }
+        output.write_all(generator.finalize().as_bytes())
+    }
+
+    fn write_pre_tag(
+        &self,
+        output: &mut dyn Write,
+        attributes: HashMap<String, String>,
+    ) -> io::Result<()> {
+        write_tag(output, "pre", attributes)
+    }
+
+    fn write_code_tag(
+        &self,
+        output: &mut dyn Write,
+        attributes: HashMap<String, String>,
+    ) -> io::Result<()> {
+        write_tag(output, "code", attributes)
+    }
+}
+
+/// Writes an opening tag with its attributes in a stable order, so that
+/// re-rendering unchanged blocks yields identical HTML.
+fn write_tag(
+    output: &mut dyn Write,
+    tag: &str,
+    attributes: HashMap<String, String>,
+) -> io::Result<()> {
+    let attributes: BTreeMap<String, String> = attributes.into_iter().collect();
+    html::write_opening_tag(output, tag, attributes)
+}
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// Returns the CSS for highlighted code: a light theme, and a dark theme for
+/// pages shown with `prefers-color-scheme: dark`.
+pub fn stylesheet() -> &'static str {
+    static STYLESHEET: OnceLock<String> = OnceLock::new();
+    STYLESHEET.get_or_init(|| {
+        let themes = ThemeSet::load_defaults();
+        let theme_css = |name: &str| {
+            css_for_theme_with_class_style(&themes.themes[name], CLASS_STYLE).unwrap_or_default()
+        };
+
+        format!(
+            "@media not (prefers-color-scheme: dark) {{\n{}}}\n\
+             @media (prefers-color-scheme: dark) {{\n{}}}\n",
+            theme_css(LIGHT_THEME),
+            theme_css(DARK_THEME)
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highlight(lang: Option<&str>, code: &str) -> String {
+        let mut output = Vec::new();
+        SyntaxHighlighter
+            .write_highlighted(&mut output, lang, code)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_known_languages_are_highlighted() {
+        let html = highlight(Some("rust"), "fn main() {}\n");
+        assert!(html.contains("<span class=\"hl-"));
+        assert!(html.contains("main"));
+    }
+
+    #[test]
+    fn test_unknown_languages_are_escaped() {
+        assert_eq!(
+            highlight(Some("mermaid"), "graph TD\n  A --> B\n"),
+            "graph TD\n  A --&gt; B\n"
+        );
+        assert_eq!(highlight(None, "<b>\n"), "&lt;b&gt;\n");
+    }
+
+    #[test]
+    fn test_stylesheet_follows_color_scheme() {
+        let css = stylesheet();
+        assert!(css.contains("@media not (prefers-color-scheme: dark)"));
+        assert!(css.contains("@media (prefers-color-scheme: dark)"));
+        assert!(css.contains(".hl-"));
+    }
+}
@@ -1,7 +1,54 @@
+use crate::front_matter::FrontMatter;
+use crate::highlight;
+use crate::markdown::Heading;
+use crate::utils;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
 const STYLES: &str = include_str!("../assets/index.css");
 const SCRIPTS: &str = include_str!("../assets/index.js");
+const MERMAID_SCRIPT: &str = include_str!("../assets/vendor/mermaid.min.js");
+const KATEX_SCRIPT: &str = include_str!("../assets/vendor/katex.min.js");
+
+/// Typesets math in pages that do not load `index.js`.
+const STATIC_MATH_SCRIPT: &str = r#"document.querySelectorAll('[data-math-style]').forEach((element) => {
+  katex.render(element.textContent, element, {
+    displayMode: element.dataset.mathStyle === 'display',
+    output: 'mathml',
+    throwOnError: false,
+  });
+});"#;
+
+/// Wraps content in the live preview page, with a table of contents built
+/// from `outline` that `index.js` keeps up to date.
+pub fn wrap_in_html_template(content: &str, title: Option<&str>, outline: &[Heading]) -> String {
+    let scripts = format!("<script>\n{}\n</script>\n", SCRIPTS);
+    render_page(content, title, &render_outline(outline), &scripts)
+}
+
+/// Wraps content in a standalone page with inlined styles and without the
+/// live update script, for publishing rendered documents as static files.
+///
+/// Pages containing math load KaTeX from `katex_url`.
+pub fn wrap_in_static_html_template(content: &str, title: Option<&str>, katex_url: &str) -> String {
+    let scripts = if contains_math(content) {
+        format!(
+            "<script src=\"{}\"></script>\n<script>\n{}\n</script>\n",
+            utils::escape_html(katex_url),
+            STATIC_MATH_SCRIPT
+        )
+    } else {
+        String::new()
+    };
+    render_page(content, title, "", &scripts)
+}
+
+/// Returns whether rendered HTML contains `$...$` or `$$...$$` math.
+pub fn contains_math(html: &str) -> bool {
+    html.contains("data-math-style=")
+}
 
-pub fn wrap_in_html_template(content: &str, title: Option<&str>) -> String {
+fn render_page(content: &str, title: Option<&str>, sidebar: &str, scripts: &str) -> String {
     let title = title.unwrap_or("Markdown Document");
 
     format!(
@@ -11,25 +58,179 @@ pub fn wrap_in_html_template(content: &str, title: Option<&str>) -> String {
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
     <title>{}</title>
-    <style>{}</style>
+    <style>{}{}</style>
 </head>
 <body>
-<main>
+{}<main>
 {}
 </main>
-<script>
-{}
-</script>
-</body>
+{}</body>
 </html>"#,
-        title, STYLES, content, SCRIPTS
+        utils::escape_html(title),
+        STYLES,
+        highlight::stylesheet(),
+        sidebar,
+        content,
+        scripts
     )
 }
 
+/// Renders the table of contents sidebar. It is hidden while the document has
+/// no headings, so that `index.js` can fill it in when some are added.
+pub fn render_outline(outline: &[Heading]) -> String {
+    let mut html = format!(
+        "<nav class=\"toc\"{}>\n<details open>\n<summary>Contents</summary>\n<ul>\n",
+        if outline.is_empty() { " hidden" } else { "" }
+    );
+    for heading in outline {
+        let href = heading
+            .anchor
+            .as_ref()
+            .map(|anchor| format!(" href=\"#{}\"", utils::escape_html(anchor)))
+            .unwrap_or_default();
+        html.push_str(&format!(
+            "<li class=\"toc-level-{}\"><a{} data-sourcepos=\"{}\">{}</a></li>\n",
+            heading.level,
+            href,
+            utils::escape_html(&heading.sourcepos),
+            utils::escape_html(&heading.text)
+        ));
+    }
+    html.push_str("</ul>\n</details>\n</nav>\n");
+    html
+}
+
 pub fn get_styles() -> String {
     STYLES.to_string()
 }
 
+/// Returns the content type and content of a bundled third-party asset, which
+/// the preview page loads from `/assets/{name}` on demand.
+pub fn vendor_asset(name: &str) -> Option<(&'static str, &'static str)> {
+    match name {
+        "mermaid.min.js" => Some(("application/javascript", MERMAID_SCRIPT)),
+        "katex.min.js" => Some(("application/javascript", KATEX_SCRIPT)),
+        _ => None,
+    }
+}
+
+/// Renders the author, date and tags of a document's front matter as a
+/// header block. Returns `None` if there is nothing to show; the title is used
+/// for the page title instead.
+pub fn render_metadata_header(front_matter: &FrontMatter, sourcepos: &str) -> Option<String> {
+    if front_matter.author.is_none() && front_matter.date.is_none() && front_matter.tags.is_empty()
+    {
+        return None;
+    }
+
+    let mut html = format!(
+        "<header class=\"document-metadata\" data-sourcepos=\"{}\">\n",
+        utils::escape_html(sourcepos)
+    );
+    if let Some(author) = &front_matter.author {
+        html.push_str(&format!(
+            "<span class=\"document-author\">{}</span>\n",
+            utils::escape_html(author)
+        ));
+    }
+    if let Some(date) = &front_matter.date {
+        html.push_str(&format!(
+            "<time class=\"document-date\" datetime=\"{0}\">{0}</time>\n",
+            utils::escape_html(date)
+        ));
+    }
+    if !front_matter.tags.is_empty() {
+        html.push_str("<ul class=\"document-tags\">\n");
+        for tag in &front_matter.tags {
+            html.push_str(&format!("<li>{}</li>\n", utils::escape_html(tag)));
+        }
+        html.push_str("</ul>\n");
+    }
+    html.push_str("</header>");
+    Some(html)
+}
+
+#[derive(Default)]
+struct TreeNode<'a> {
+    directories: BTreeMap<String, TreeNode<'a>>,
+    files: BTreeMap<String, (&'a str, &'a str)>, // name -> (id, filepath)
+}
+
+/// Renders `(id, filepath)` pairs as a navigable tree of nested lists.
+///
+/// Paths are shown relative to their deepest common directory, which is
+/// displayed above the tree.
+pub fn render_document_tree(documents: &[(String, String)]) -> String {
+    let mut root: Option<PathBuf> = None;
+    for (_, filepath) in documents {
+        let parent = Path::new(filepath).parent().unwrap_or(Path::new(""));
+        root = Some(match root {
+            None => parent.to_path_buf(),
+            Some(mut current) => {
+                while !parent.starts_with(&current) {
+                    if !current.pop() {
+                        break;
+                    }
+                }
+                current
+            }
+        });
+    }
+    let root = root.unwrap_or_default();
+
+    let mut tree = TreeNode::default();
+    for (id, filepath) in documents {
+        let relative = Path::new(filepath)
+            .strip_prefix(&root)
+            .unwrap_or(Path::new(filepath));
+        let mut components: Vec<String> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect();
+        let Some(name) = components.pop() else {
+            continue;
+        };
+
+        let mut node = &mut tree;
+        for directory in components {
+            node = node.directories.entry(directory).or_default();
+        }
+        node.files.insert(name, (id, filepath));
+    }
+
+    let mut html = String::from("<nav class=\"document-tree\">\n");
+    if !documents.is_empty() {
+        html.push_str(&format!(
+            "<p class=\"document-root\">{}</p>\n",
+            utils::escape_html(&root.to_string_lossy())
+        ));
+    }
+    render_tree_node(&tree, &mut html);
+    html.push_str("</nav>");
+    html
+}
+
+fn render_tree_node(node: &TreeNode, html: &mut String) {
+    html.push_str("<ul>\n");
+    for (name, child) in &node.directories {
+        html.push_str(&format!(
+            "<li><details open><summary>{}/</summary>\n",
+            utils::escape_html(name)
+        ));
+        render_tree_node(child, html);
+        html.push_str("</details></li>\n");
+    }
+    for (name, (id, filepath)) in &node.files {
+        html.push_str(&format!(
+            "<li><a href=\"/document/{}\" title=\"{}\">{}</a></li>\n",
+            id,
+            utils::escape_html(filepath),
+            utils::escape_html(name)
+        ));
+    }
+    html.push_str("</ul>\n");
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -37,7 +238,7 @@ mod tests {
     #[test]
     fn test_wrap_with_default_title() {
         let content = "<h1>Test Content</h1>";
-        let html = wrap_in_html_template(content, None);
+        let html = wrap_in_html_template(content, None, &[]);
 
         assert!(html.contains("<!DOCTYPE html>"));
         assert!(html.contains("<title>Markdown Document</title>"));
@@ -50,7 +251,7 @@ mod tests {
     fn test_wrap_with_custom_title() {
         let content = "<p>Some content</p>";
         let title = "My Custom Title";
-        let html = wrap_in_html_template(content, Some(title));
+        let html = wrap_in_html_template(content, Some(title), &[]);
 
         assert!(html.contains("<!DOCTYPE html>"));
         assert!(html.contains("<title>My Custom Title</title>"));
@@ -60,7 +261,7 @@ mod tests {
     #[test]
     fn test_html_structure() {
         let content = "<div>test</div>";
-        let html = wrap_in_html_template(content, Some("Test"));
+        let html = wrap_in_html_template(content, Some("Test"), &[]);
 
         // Check proper HTML5 structure
         assert!(html.contains("<!DOCTYPE html>"));
@@ -70,4 +271,88 @@ mod tests {
             .contains(r#"<meta name="viewport" content="width=device-width, initial-scale=1.0">"#));
         assert!(html.contains("</html>"));
     }
+
+    #[test]
+    fn test_static_template_has_no_scripts() {
+        let html = wrap_in_static_html_template("<p>Static</p>", Some("Export"), "katex.min.js");
+
+        assert!(html.contains("<title>Export</title>"));
+        assert!(html.contains("<p>Static</p>"));
+        assert!(html.contains("<style>"));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("EventSource"));
+    }
+
+    #[test]
+    fn test_static_template_typesets_math() {
+        let content = r#"<p><span data-math-style="inline">x^2</span></p>"#;
+        let html = wrap_in_static_html_template(content, None, "../katex.min.js");
+
+        assert!(html.contains(r#"<script src="../katex.min.js"></script>"#));
+        assert!(html.contains("katex.render("));
+        assert!(!html.contains("EventSource"));
+    }
+
+    #[test]
+    fn test_outline_sidebar() {
+        let outline = vec![
+            Heading {
+                level: 1,
+                text: "Intro".to_string(),
+                anchor: Some("intro".to_string()),
+                sourcepos: "1:1-1:7".to_string(),
+            },
+            Heading {
+                level: 2,
+                text: "A <b> tag".to_string(),
+                anchor: None,
+                sourcepos: "3:1-3:12".to_string(),
+            },
+        ];
+        let html = wrap_in_html_template("<h1>Intro</h1>", None, &outline);
+
+        assert!(html.contains("<nav class=\"toc\">"));
+        assert!(html.contains(
+            r##"<li class="toc-level-1"><a href="#intro" data-sourcepos="1:1-1:7">Intro</a></li>"##
+        ));
+        assert!(html.contains(
+            r#"<li class="toc-level-2"><a data-sourcepos="3:1-3:12">A &lt;b&gt; tag</a></li>"#
+        ));
+        assert!(html.find("class=\"toc\"").unwrap() < html.find("<main>").unwrap());
+
+        assert!(render_outline(&[]).starts_with("<nav class=\"toc\" hidden>"));
+        assert!(!wrap_in_static_html_template("<p>Static</p>", None, "").contains("<nav"));
+    }
+
+    #[test]
+    fn test_document_tree_nests_directories() {
+        let documents = vec![
+            ("readme-md-1".to_string(), "/docs/readme.md".to_string()),
+            ("intro-md-2".to_string(), "/docs/guide/intro.md".to_string()),
+            ("setup-md-3".to_string(), "/docs/guide/setup.md".to_string()),
+        ];
+        let html = render_document_tree(&documents);
+
+        assert!(html.contains(r#"<p class="document-root">/docs</p>"#));
+        assert!(html.contains("<summary>guide/</summary>"));
+        assert!(html.contains(
+            r#"<a href="/document/intro-md-2" title="/docs/guide/intro.md">intro.md</a>"#
+        ));
+
+        // Files inside a directory are listed after the directory entry
+        let guide = html.find("guide/").unwrap();
+        let setup = html.find("setup.md").unwrap();
+        let readme = html.find(">readme.md<").unwrap();
+        assert!(guide < setup);
+        assert!(setup < readme);
+    }
+
+    #[test]
+    fn test_document_tree_empty() {
+        let html = render_document_tree(&[]);
+
+        assert!(html.contains("<ul>"));
+        assert!(!html.contains("<li>"));
+        assert!(!html.contains("document-root"));
+    }
 }
@@ -1,30 +1,40 @@
 use axum::{
-    extract::Path,
+    extract::{ws, Path},
     http::{header, HeaderMap, StatusCode},
     response::{
         sse::{Event, Sse},
         IntoResponse,
     },
-    routing::{delete, get, post},
+    routing::{get, post, put},
     Router,
 };
 use facet::Facet;
 use notify::{RecommendedWatcher, RecursiveMode};
 use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
-use std::collections::HashMap;
+use sourcepos::{EditorPosition, SourcePos};
+use std::collections::{HashMap, HashSet};
 use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::Path as FsPath;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::sync::broadcast;
 use tokio_stream::Stream;
 
+pub mod config;
+pub mod export;
+pub mod front_matter;
+pub mod highlight;
 pub mod html_template;
 pub mod markdown;
+pub mod patch;
+pub mod sourcepos;
 pub mod utils;
 
 #[derive(facet::Facet)]
 struct FileChangedResponse {
-    html: String,
+    blocks: Vec<patch::BlockPatch>,
+    outline: Vec<markdown::Heading>,
 }
 
 #[derive(Clone, Debug)]
@@ -34,39 +44,644 @@ pub enum DocumentEvent {
     },
     PositionUpdate {
         document_id: String,
-        sourcepos: String,
+        position: ResolvedPosition,
     },
+    /// The preview was scrolled so that the block at `sourcepos` is at the
+    /// top. Sent to editors, not to other previews.
+    PreviewScrolled {
+        document_id: String,
+        sourcepos: SourcePos,
+    },
+    /// The document's file is gone. Documents in watched directories are
+    /// removed; others are shown again if the file reappears.
+    FileDeleted {
+        document_id: String,
+        filepath: String,
+    },
+    /// The document's file was moved to `filepath`.
+    FileRenamed {
+        document_id: String,
+        filepath: String,
+    },
+}
+
+impl DocumentEvent {
+    pub fn document_id(&self) -> &str {
+        match self {
+            DocumentEvent::FileChanged { document_id }
+            | DocumentEvent::PositionUpdate { document_id, .. }
+            | DocumentEvent::PreviewScrolled { document_id, .. }
+            | DocumentEvent::FileDeleted { document_id, .. }
+            | DocumentEvent::FileRenamed { document_id, .. } => document_id,
+        }
+    }
+}
+
+/// An editor position mapped onto the rendered page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedPosition {
+    pub position: EditorPosition,
+    /// The element the cursor is in, or the nearest one, to scroll to.
+    pub target: SourcePos,
+    /// The top-level blocks to highlight: those the selection touches, or
+    /// the one the target is in.
+    pub highlight: Vec<SourcePos>,
+}
+
+/// Payload of `position` events.
+#[derive(facet::Facet)]
+struct PositionResponse {
+    /// The element to scroll to.
+    sourcepos: String,
+    cursor: String,
+    selection: Option<String>,
+    highlight: Vec<String>,
+}
+
+impl PositionResponse {
+    fn message(transport: Transport, resolved: &ResolvedPosition) -> UpdateMessage {
+        let response = PositionResponse {
+            sourcepos: resolved.target.to_string(),
+            cursor: resolved.position.cursor.to_string(),
+            selection: resolved
+                .position
+                .selection
+                .map(|selection| selection.to_string()),
+            highlight: resolved
+                .highlight
+                .iter()
+                .map(|sourcepos| sourcepos.to_string())
+                .collect(),
+        };
+        UpdateMessage::new(transport, "position", response)
+    }
+}
+
+/// Payload of `file_deleted` and `file_renamed` events: where the file was, or
+/// where it is now.
+#[derive(facet::Facet)]
+struct FileMovedResponse {
+    filepath: String,
+    title: String,
+}
+
+/// Payload of `scroll` events.
+#[derive(facet::Facet)]
+struct ScrollResponse {
+    sourcepos: String,
+}
+
+impl ScrollResponse {
+    fn message(transport: Transport, sourcepos: SourcePos) -> UpdateMessage {
+        let response = ScrollResponse {
+            sourcepos: sourcepos.to_string(),
+        };
+        UpdateMessage::new(transport, "scroll", response)
+    }
+}
+
+/// A [`DocumentEvent`] numbered in the order it was sent for its document.
+#[derive(Clone, Debug)]
+struct SentEvent {
+    id: u64,
+    event: DocumentEvent,
+}
+
+/// The ids of the events sent for a document so far, which reconnecting SSE
+/// clients are compared against.
+#[derive(Clone, Copy, Debug, Default)]
+struct EventIds {
+    last: u64,
+    last_file_change: u64,
+}
+
+impl EventIds {
+    /// Returns whether a client that last saw event `seen` missed a file
+    /// change. Ids from before a restart of the server count as missing it.
+    fn missed_file_change(&self, seen: u64) -> bool {
+        seen < self.last_file_change || seen > self.last
+    }
+}
+
+/// How a client receives [`UpdateMessage`]s.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Transport {
+    Sse,
+    Socket,
+}
+
+/// An event sent to a preview or editor: an SSE event named `event`, or a
+/// [`SocketMessage`].
+struct UpdateMessage {
+    event: &'static str,
+    /// The [`SentEvent`] id, which SSE clients resume from.
+    id: Option<u64>,
+    /// JSON payload for SSE, or the whole [`SocketMessage`] for WebSockets.
+    data: String,
+}
+
+/// An [`UpdateMessage`] as sent over a WebSocket.
+#[derive(facet::Facet)]
+struct SocketMessage<T> {
+    event: String,
+    data: T,
+}
+
+impl UpdateMessage {
+    fn new<T: Facet<'static>>(transport: Transport, event: &'static str, payload: T) -> Self {
+        let data = match transport {
+            Transport::Sse => facet_json::to_string(&payload),
+            Transport::Socket => facet_json::to_string(&SocketMessage {
+                event: event.to_string(),
+                data: payload,
+            }),
+        };
+        Self {
+            event,
+            id: None,
+            data,
+        }
+    }
+
+    fn with_id(mut self, id: u64) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    fn sse_event(self) -> Event {
+        let event = Event::default().event(self.event).data(self.data);
+        match self.id {
+            Some(id) => event.id(id.to_string()),
+            None => event,
+        }
+    }
+
+    fn socket_message(self) -> ws::Message {
+        ws::Message::Text(self.data.into())
+    }
+}
+
+/// The last render of a document, shared by page loads and SSE subscribers.
+struct CachedRender {
+    content_hash: u64,
+    blocks: Arc<Vec<markdown::RenderedBlock>>,
+    outline: Arc<Vec<markdown::Heading>>,
+    /// The ranges of every block element of the page, which positions are resolved
+    /// against.
+    block_positions: Vec<SourcePos>,
+    front_matter: Option<front_matter::FrontMatter>,
+    /// Cleared when the watcher reports a change, so that the next request
+    /// re-reads the file.
+    fresh: bool,
+}
+
+/// The size and modification time of a file, which renaming it keeps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FileStamp {
+    len: u64,
+    modified: std::time::SystemTime,
+}
+
+impl FileStamp {
+    fn of(path: &FsPath) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok()?,
+        })
+    }
+}
+
+/// Why a document's current content could not be rendered.
+#[derive(Debug)]
+pub enum RenderError {
+    DocumentNotFound,
+    ReadFailed,
+}
+
+impl RenderError {
+    fn message(&self) -> &'static str {
+        match self {
+            RenderError::DocumentNotFound => "Document not found",
+            RenderError::ReadFailed => "Error reading file",
+        }
+    }
 }
 
 pub struct DocumentStore {
-    filepath_map: HashMap<String, String>,    // id -> filepath
-    document_id_map: HashMap<String, String>, // filepath -> id
-    position_map: HashMap<String, String>,    // id -> sourcepos
-    event_tx: Option<broadcast::Sender<DocumentEvent>>,
+    filepath_map: HashMap<String, String>,         // id -> filepath
+    document_id_map: HashMap<String, String>,      // filepath -> id
+    position_map: HashMap<String, EditorPosition>, // id -> editor position
+    dependency_map: HashMap<String, HashSet<String>>, // asset or included filepath -> ids of documents using it
+    render_cache: HashMap<String, CachedRender>,      // id -> last render
+    render_errors: HashMap<String, String>,           // id -> last render error
+    subscriber_map: HashMap<String, usize>,           // id -> connected SSE clients
+    extensions_map: HashMap<String, config::ExtensionSet>, // id -> extensions overriding the default
+    unsaved_content: HashMap<String, String>, // id -> editor buffer shown instead of the file
+    watched_directories: Vec<String>,
+    channels: HashMap<String, broadcast::Sender<SentEvent>>, // id -> events for connected clients
+    event_ids: HashMap<String, EventIds>,                    // id -> ids of the events sent so far
+    render_generations: HashMap<String, u64>, // id -> bumped whenever its render goes stale
+    render_locks: HashMap<String, Arc<Mutex<()>>>, // id -> held while the document renders
+    file_stamps: HashMap<String, FileStamp>,  // id -> its file when last seen, to spot renames
+}
+
+impl DocumentStore {
+    fn insert_document(&mut self, id: String, filepath: String) {
+        self.record_file_stamp(&id, &filepath);
+        self.filepath_map.insert(id.clone(), filepath.clone());
+        self.document_id_map.insert(filepath, id.clone());
+        self.position_map.entry(id).or_insert(EditorPosition::START); // Default position
+    }
+
+    fn remove_document(&mut self, id: &str) -> Option<String> {
+        let filepath = self.filepath_map.remove(id)?;
+        self.document_id_map.remove(&filepath);
+        self.position_map.remove(id);
+        self.render_cache.remove(id);
+        self.render_generations.remove(id);
+        self.render_locks.remove(id);
+        self.file_stamps.remove(id);
+        self.render_errors.remove(id);
+        self.extensions_map.remove(id);
+        self.unsaved_content.remove(id);
+        self.event_ids.remove(id);
+        self.clear_dependencies(id);
+        Some(filepath)
+    }
+
+    /// Makes the next request re-read document `id`. Its render is reused if
+    /// the content turns out to be unchanged.
+    fn invalidate_render(&mut self, id: &str) {
+        if let Some(cached) = self.render_cache.get_mut(id) {
+            cached.fresh = false;
+        }
+        *self.render_generations.entry(id.to_string()).or_default() += 1;
+    }
+
+    /// Makes the next request re-render document `id`, for changes that do
+    /// not show in its content, such as those to files it includes.
+    fn discard_render(&mut self, id: &str) {
+        self.render_cache.remove(id);
+        *self.render_generations.entry(id.to_string()).or_default() += 1;
+    }
+
+    /// Remembers the size and modification time of the file of document
+    /// `id`, so that it can be recognised if the file is renamed before the
+    /// document is rendered.
+    fn record_file_stamp(&mut self, id: &str, filepath: &str) {
+        if let Some(stamp) = FileStamp::of(FsPath::new(filepath)) {
+            self.file_stamps.insert(id.to_string(), stamp);
+        }
+    }
+
+    fn render_generation(&self, id: &str) -> u64 {
+        self.render_generations.get(id).copied().unwrap_or_default()
+    }
+
+    /// Makes the next request re-render document `id`, and the documents
+    /// including it, and tells their previews.
+    fn mark_changed(&mut self, id: &str) {
+        self.invalidate_render(id);
+        self.send(DocumentEvent::FileChanged {
+            document_id: id.to_string(),
+        });
+
+        let dependents = self
+            .filepath_map
+            .get(id)
+            .and_then(|filepath| self.dependency_map.get(filepath))
+            .cloned()
+            .unwrap_or_default();
+        for dependent in dependents {
+            if dependent != id {
+                self.discard_render(&dependent);
+                self.send(DocumentEvent::FileChanged {
+                    document_id: dependent,
+                });
+            }
+        }
+    }
+
+    /// Numbers `event` and sends it to the clients of its document, if it has
+    /// any. Events are numbered either way, so that clients reconnecting later
+    /// know what they missed.
+    fn send(&mut self, event: DocumentEvent) {
+        let ids = self
+            .event_ids
+            .entry(event.document_id().to_string())
+            .or_default();
+        ids.last += 1;
+        if let DocumentEvent::FileChanged { .. } = event {
+            ids.last_file_change = ids.last;
+        }
+
+        let id = ids.last;
+        if let Some(tx) = self.channels.get(event.document_id()) {
+            let _ = tx.send(SentEvent { id, event });
+        }
+    }
+
+    fn clear_dependencies(&mut self, id: &str) {
+        self.dependency_map.retain(|_, ids| {
+            ids.remove(id);
+            !ids.is_empty()
+        });
+    }
+
+    /// Returns the directory a document may serve assets from: the watched
+    /// directory containing it, or else the document's own directory.
+    fn document_root(&self, id: &str) -> Option<String> {
+        let filepath = FsPath::new(self.filepath_map.get(id)?);
+        let root = self
+            .watched_directories
+            .iter()
+            .find(|dir| filepath.starts_with(dir))
+            .map(FsPath::new)
+            .or_else(|| filepath.parent())?;
+        Some(root.to_string_lossy().to_string())
+    }
+
+    fn is_in_watched_directory(&self, path: &FsPath) -> bool {
+        self.watched_directories
+            .iter()
+            .any(|dir| path.starts_with(dir))
+    }
+
+    /// Registers a markdown file discovered inside a watched directory.
+    fn register_discovered_file(&mut self, path: &FsPath) {
+        let filepath = path.to_string_lossy().to_string();
+        if self.document_id_map.contains_key(&filepath) {
+            return;
+        }
+
+        let id = utils::generate_document_id(&filepath);
+        self.insert_document(id, filepath);
+    }
+
+    /// Updates the store for the paths the file watcher reported in one batch
+    /// and returns the events to send.
+    ///
+    /// A document whose file disappears in the same batch as a markdown file
+    /// with the same content appears is taken to have been renamed. Documents
+    /// deleted from a watched directory are dropped; others stay registered
+    /// until their file reappears.
+    fn handle_path_changes(&mut self, paths: &[&FsPath]) -> Vec<DocumentEvent> {
+        let mut changed_ids = Vec::new();
+        let mut deleted_ids = Vec::new();
+        // Re-rendered although their own file is unchanged
+        let mut refreshed_ids = Vec::new();
+        let mut appeared = Vec::new();
+
+        for &path in paths {
+            let filepath = path.to_string_lossy().to_string();
+
+            if let Some(id) = self.document_id_map.get(&filepath).cloned() {
+                if path.exists() {
+                    changed_ids.push(id);
+                } else {
+                    deleted_ids.push(id);
+                }
+            } else if path.is_file() && utils::is_markdown_file(path) {
+                appeared.push(path);
+            } else if self.is_in_watched_directory(path) {
+                if path.is_dir() {
+                    for file in utils::find_markdown_files(path) {
+                        self.register_discovered_file(&file);
+                    }
+                } else if !path.exists() {
+                    // A removed directory takes all of its documents with it
+                    deleted_ids.extend(
+                        self.filepath_map
+                            .iter()
+                            .filter(|(_, filepath)| FsPath::new(filepath).starts_with(path))
+                            .map(|(id, _)| id.clone()),
+                    );
+                }
+            }
+
+            // Documents referencing a changed asset or including a changed
+            // file re-render to pick it up
+            if let Some(ids) = self.dependency_map.get(&filepath) {
+                refreshed_ids.extend(ids.iter().cloned());
+            }
+        }
+
+        let mut events = Vec::new();
+        for path in appeared {
+            if let Some(index) = deleted_ids
+                .iter()
+                .position(|id| self.is_renamed_to(id, path))
+            {
+                let id = deleted_ids.remove(index);
+                self.rename_document(&id, path);
+                events.push(DocumentEvent::FileRenamed {
+                    document_id: id.clone(),
+                    filepath: path.to_string_lossy().to_string(),
+                });
+                refreshed_ids.push(id);
+            } else if self.is_in_watched_directory(path) {
+                self.register_discovered_file(path);
+            }
+        }
+
+        changed_ids.sort();
+        changed_ids.dedup();
+        for id in changed_ids {
+            if let Some(filepath) = self.filepath_map.get(&id).cloned() {
+                self.record_file_stamp(&id, &filepath);
+            }
+            // Saving replaces any unsaved content the editor pushed
+            self.unsaved_content.remove(&id);
+            self.invalidate_render(&id);
+            events.push(DocumentEvent::FileChanged { document_id: id });
+        }
+
+        deleted_ids.sort();
+        deleted_ids.dedup();
+        for id in deleted_ids {
+            let Some(filepath) = self.filepath_map.get(&id).cloned() else {
+                continue;
+            };
+            if self.is_in_watched_directory(FsPath::new(&filepath)) {
+                self.remove_document(&id);
+            } else {
+                self.invalidate_render(&id);
+            }
+            events.push(DocumentEvent::FileDeleted {
+                document_id: id,
+                filepath,
+            });
+        }
+
+        for id in refreshed_ids {
+            self.discard_render(&id);
+            let already_sent = events.iter().any(|event| {
+                matches!(event, DocumentEvent::FileChanged { document_id } if *document_id == id)
+            });
+            if !already_sent && self.filepath_map.contains_key(&id) {
+                events.push(DocumentEvent::FileChanged { document_id: id });
+            }
+        }
+
+        events
+    }
+
+    /// Returns whether the file at `path` could be document `id` after a
+    /// rename: it has the size and modification time the document's file was
+    /// last seen with or the content it was last rendered from, and is
+    /// watched wherever the document was.
+    fn is_renamed_to(&self, id: &str, path: &FsPath) -> bool {
+        let Some(filepath) = self.filepath_map.get(id) else {
+            return false;
+        };
+        let old_path = FsPath::new(filepath);
+        let still_watched = old_path.parent() == path.parent()
+            || (self.is_in_watched_directory(old_path) && self.is_in_watched_directory(path));
+
+        let same_stamp = || {
+            self.file_stamps
+                .get(id)
+                .is_some_and(|&stamp| FileStamp::of(path) == Some(stamp))
+        };
+        let same_content = || {
+            self.render_cache.get(id).is_some_and(|cached| {
+                std::fs::read_to_string(path)
+                    .is_ok_and(|content| utils::hash_content(&content) == cached.content_hash)
+            })
+        };
+        still_watched && (same_stamp() || same_content())
+    }
+
+    /// Moves document `id` to `path`, keeping its id so that open previews
+    /// follow it, along with the documents that include it.
+    fn rename_document(&mut self, id: &str, path: &FsPath) {
+        let filepath = path.to_string_lossy().to_string();
+        if let Some(old_filepath) = self.filepath_map.insert(id.to_string(), filepath.clone()) {
+            self.document_id_map.remove(&old_filepath);
+            if let Some(ids) = self.dependency_map.remove(&old_filepath) {
+                self.dependency_map
+                    .entry(filepath.clone())
+                    .or_default()
+                    .extend(ids);
+            }
+        }
+        self.document_id_map.insert(filepath, id.to_string());
+    }
+
+    /// Returns whether any document outside watched directories lives
+    /// directly in `dir`, and so relies on a watch on it.
+    fn has_documents_watched_through(&self, dir: &FsPath) -> bool {
+        self.filepath_map.values().any(|filepath| {
+            let path = FsPath::new(filepath);
+            path.parent() == Some(dir) && !self.is_in_watched_directory(path)
+        })
+    }
 }
 
 #[derive(Clone)]
 pub struct AppState {
     store: Arc<Mutex<DocumentStore>>,
-    event_tx: broadcast::Sender<DocumentEvent>,
     file_watcher: Arc<Mutex<Option<Debouncer<RecommendedWatcher>>>>,
+    browser_command: Arc<str>,
+    default_extensions: config::ExtensionSet,
+    server_addr: SocketAddr,
 }
 
+/// Command used to open documents in a browser unless configured otherwise.
+#[cfg(target_os = "macos")]
+pub const DEFAULT_BROWSER_COMMAND: &str = "open";
+#[cfg(not(target_os = "macos"))]
+pub const DEFAULT_BROWSER_COMMAND: &str = "xdg-open";
+
 impl AppState {
     pub fn new() -> Self {
-        let (event_tx, _) = broadcast::channel(100);
-        let event_tx_clone = event_tx.clone();
-
         Self {
             store: Arc::new(Mutex::new(DocumentStore {
                 filepath_map: HashMap::new(),
                 document_id_map: HashMap::new(),
                 position_map: HashMap::new(),
-                event_tx: Some(event_tx_clone),
+                dependency_map: HashMap::new(),
+                render_cache: HashMap::new(),
+                render_errors: HashMap::new(),
+                subscriber_map: HashMap::new(),
+                extensions_map: HashMap::new(),
+                unsaved_content: HashMap::new(),
+                watched_directories: Vec::new(),
+                channels: HashMap::new(),
+                event_ids: HashMap::new(),
+                render_generations: HashMap::new(),
+                render_locks: HashMap::new(),
+                file_stamps: HashMap::new(),
             })),
-            event_tx,
             file_watcher: Arc::new(Mutex::new(None)),
+            browser_command: Arc::from(DEFAULT_BROWSER_COMMAND),
+            default_extensions: config::ExtensionSet::default(),
+            server_addr: SocketAddr::from(([127, 0, 0, 1], 3030)),
+        }
+    }
+
+    /// Sets the markdown extensions used by documents that do not choose
+    /// their own.
+    pub fn with_extensions(mut self, extensions: config::ExtensionSet) -> Self {
+        self.default_extensions = extensions;
+        self
+    }
+
+    pub fn default_extensions(&self) -> config::ExtensionSet {
+        self.default_extensions
+    }
+
+    /// Returns the markdown extensions document `id` is rendered with.
+    pub fn get_document_extensions(&self, id: &str) -> config::ExtensionSet {
+        self.store
+            .lock()
+            .unwrap()
+            .extensions_map
+            .get(id)
+            .copied()
+            .unwrap_or(self.default_extensions)
+    }
+
+    /// Renders document `id` with `extensions` from now on, refreshing open
+    /// previews if they change.
+    pub fn set_document_extensions(&self, id: &str, extensions: config::ExtensionSet) {
+        let mut store = self.store.lock().unwrap();
+        let previous = store
+            .extensions_map
+            .insert(id.to_string(), extensions)
+            .unwrap_or(self.default_extensions);
+        if previous == extensions {
+            return;
         }
+
+        store.discard_render(id);
+        store.send(DocumentEvent::FileChanged {
+            document_id: id.to_string(),
+        });
+    }
+
+    /// Sets the command that `POST /api/document/{id}/open` launches with the
+    /// document URL.
+    pub fn with_browser_command(mut self, browser_command: &str) -> Self {
+        self.browser_command = Arc::from(browser_command);
+        self
+    }
+
+    /// Sets the address the server is bound to, which URLs opened in the
+    /// browser point at.
+    pub fn with_server_addr(mut self, server_addr: SocketAddr) -> Self {
+        self.server_addr = server_addr;
+        self
+    }
+
+    /// Returns the URL of `path` on this server.
+    pub fn server_url(&self, path: &str) -> String {
+        format!("http://{}{}", self.server_addr, path)
+    }
+
+    pub fn open_in_browser(&self, url: &str) -> std::io::Result<()> {
+        utils::open_in_browser(&self.browser_command, url)
     }
 
     fn init_file_watcher(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -76,23 +691,18 @@ impl AppState {
             return Ok(()); // Already initialized
         }
 
-        let event_tx = self.event_tx.clone();
         let store = self.store.clone();
 
         let debouncer = new_debouncer(
             Duration::from_millis(300),
             move |res: DebounceEventResult| {
-                if let Ok(events) = res {
-                    for event in events {
-                        if let Some(path) = event.path.to_str() {
-                            if let Ok(store_guard) = store.lock() {
-                                if let Some(doc_id) = store_guard.document_id_map.get(path) {
-                                    let _ = event_tx.send(DocumentEvent::FileChanged {
-                                        document_id: doc_id.clone(),
-                                    });
-                                }
-                            }
-                        }
+                if let Ok(events) = res
+                    && let Ok(mut store_guard) = store.lock()
+                {
+                    let paths: Vec<&FsPath> =
+                        events.iter().map(|event| event.path.as_path()).collect();
+                    for event in store_guard.handle_path_changes(&paths) {
+                        store_guard.send(event);
                     }
                 }
             },
@@ -105,29 +715,37 @@ impl AppState {
     pub fn watch_file(
         &self,
         filepath: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.watch_path(filepath, RecursiveMode::NonRecursive)
+    }
+
+    pub fn watch_directory(
+        &self,
+        dirpath: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.watch_path(dirpath, RecursiveMode::Recursive)
+    }
+
+    fn watch_path(
+        &self,
+        path: &str,
+        mode: RecursiveMode,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Initialize watcher if needed
         self.init_file_watcher()?;
 
         let mut watcher_guard = self.file_watcher.lock().unwrap();
         if let Some(ref mut debouncer) = *watcher_guard {
-            debouncer
-                .watcher()
-                .watch(std::path::Path::new(filepath), RecursiveMode::NonRecursive)?;
+            debouncer.watcher().watch(FsPath::new(path), mode)?;
         }
 
         Ok(())
     }
 
-    pub fn unwatch_file(
-        &self,
-        filepath: &str,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    pub fn unwatch_path(&self, path: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut watcher_guard = self.file_watcher.lock().unwrap();
         if let Some(ref mut debouncer) = *watcher_guard {
-            debouncer
-                .watcher()
-                .unwatch(std::path::Path::new(filepath))?;
+            debouncer.watcher().unwatch(FsPath::new(path))?;
         }
 
         Ok(())
@@ -150,106 +768,620 @@ impl AppState {
         // Convert to absolute path
         let absolute_path = utils::to_absolute_path(&filepath);
 
-        {
+        let covered_by_directory = {
             let mut store = self.store.lock().unwrap();
-            store.filepath_map.insert(id.clone(), absolute_path.clone());
-            store
-                .document_id_map
-                .insert(absolute_path.clone(), id.clone());
-            store.position_map.insert(id.clone(), "1:1-1:1".to_string()); // Default position
-        }
+            store.insert_document(id, absolute_path.clone());
+            store.is_in_watched_directory(FsPath::new(&absolute_path))
+        };
 
-        // Start watching the file
-        if let Err(e) = self.watch_file(&absolute_path) {
+        // Start watching the file's directory unless a directory watch already
+        // covers it. Watching the directory rather than the file itself keeps
+        // the watch working when the file is deleted and recreated or replaced
+        // by a rename, as many editors save, and shows where it is renamed to.
+        if !covered_by_directory
+            && let Some(parent) = FsPath::new(&absolute_path).parent()
+            && let Err(e) = self.watch_path(&parent.to_string_lossy(), RecursiveMode::NonRecursive)
+        {
             eprintln!("Failed to watch file {}: {}", absolute_path, e);
         }
     }
 
-    pub fn remove_document(&self, id: &str) -> Option<String> {
-        let filepath = {
+    /// Registers every markdown file under `dirpath` and watches the directory
+    /// recursively so that files created or deleted later are picked up.
+    ///
+    /// Returns the ids of the documents found at registration time.
+    pub fn add_directory(&self, dirpath: &str) -> Vec<String> {
+        let absolute_path = utils::to_absolute_path(dirpath);
+
+        let ids = {
             let mut store = self.store.lock().unwrap();
-            if let Some(filepath) = store.filepath_map.remove(id) {
-                store.document_id_map.remove(&filepath);
-                store.position_map.remove(id);
-                Some(filepath)
-            } else {
-                None
+            if !store.watched_directories.contains(&absolute_path) {
+                store.watched_directories.push(absolute_path.clone());
             }
+
+            utils::find_markdown_files(FsPath::new(&absolute_path))
+                .into_iter()
+                .map(|file| {
+                    store.register_discovered_file(&file);
+                    store.document_id_map[&*file.to_string_lossy()].clone()
+                })
+                .collect()
         };
 
-        // Stop watching the file if it was removed
-        if let Some(ref path) = filepath {
-            if let Err(e) = self.unwatch_file(path) {
-                eprintln!("Failed to unwatch file {}: {}", path, e);
-            }
+        if let Err(e) = self.watch_directory(&absolute_path) {
+            eprintln!("Failed to watch directory {}: {}", absolute_path, e);
+        }
+
+        ids
+    }
+
+    pub fn remove_document(&self, id: &str) -> Option<String> {
+        let (filepath, unwatched_directory) = {
+            let mut store = self.store.lock().unwrap();
+            let filepath = store.remove_document(id);
+            let unwatched = filepath
+                .as_deref()
+                .map(FsPath::new)
+                .filter(|path| !store.is_in_watched_directory(path))
+                .and_then(FsPath::parent)
+                .filter(|dir| !store.has_documents_watched_through(dir))
+                .map(|dir| dir.to_string_lossy().to_string());
+            (filepath, unwatched)
+        };
+
+        // Stop watching the file's directory once no document needs it
+        if let Some(ref dir) = unwatched_directory
+            && let Err(e) = self.unwatch_path(dir)
+        {
+            eprintln!("Failed to unwatch directory {}: {}", dir, e);
         }
 
         filepath
     }
 
-    pub fn update_position(&self, id: &str, sourcepos: String) {
+    /// Stores the editor position for document `id` and broadcasts where it
+    /// is on the page. Returns `false` if no such document is registered.
+    pub fn update_position(&self, id: &str, position: EditorPosition) -> bool {
+        let resolved = self.resolve_position(id, position);
+
         let mut store = self.store.lock().unwrap();
-        store.position_map.insert(id.to_string(), sourcepos.clone());
+        if !store.filepath_map.contains_key(id) {
+            return false;
+        }
+        store.position_map.insert(id.to_string(), position);
 
         // Broadcast position update
-        if let Some(ref tx) = store.event_tx {
-            let _ = tx.send(DocumentEvent::PositionUpdate {
-                document_id: id.to_string(),
-                sourcepos,
-            });
-        }
+        store.send(DocumentEvent::PositionUpdate {
+            document_id: id.to_string(),
+            position: resolved,
+        });
+        true
     }
 
-    pub fn get_position(&self, id: &str) -> Option<String> {
-        self.store.lock().unwrap().position_map.get(id).cloned()
-    }
+    /// Maps an editor position onto the rendered elements: the cursor to the
+    /// `data-sourcepos` of the element that contains it, or the nearest one,
+    /// and the selection to the blocks it touches. Positions that cannot be
+    /// resolved are used as they are.
+    pub fn resolve_position(&self, id: &str, position: EditorPosition) -> ResolvedPosition {
+        let unresolved = ResolvedPosition {
+            position,
+            target: position.cursor,
+            highlight: vec![position.cursor],
+        };
+        if self.render_document(id).is_err() {
+            return unresolved;
+        }
 
-    pub fn get_all_documents(&self) -> Vec<(String, String)> {
-        self.store
-            .lock()
-            .unwrap()
-            .filepath_map
+        let store = self.store.lock().unwrap();
+        let Some(cached) = store.render_cache.get(id) else {
+            return unresolved;
+        };
+
+        let target =
+            sourcepos::resolve(position.cursor, &cached.block_positions).unwrap_or(position.cursor);
+        // Whole top-level blocks are highlighted: those the selection touches,
+        // or the one the target is in
+        let blocks: Vec<SourcePos> = cached
+            .blocks
             .iter()
-            .map(|(id, filepath)| (id.clone(), filepath.clone()))
-            .collect()
+            .filter_map(|block| block.sourcepos.parse().ok())
+            .collect();
+        let highlight = [position.selection, Some(target)]
+            .into_iter()
+            .flatten()
+            .map(|range| sourcepos::overlapping(range, &blocks))
+            .find(|blocks| !blocks.is_empty())
+            .unwrap_or_else(|| vec![target]);
+
+        ResolvedPosition {
+            position,
+            target,
+            highlight,
+        }
     }
-}
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self::new()
+    /// Broadcasts the position a preview of document `id` was scrolled to.
+    /// Returns false if the document is not registered.
+    pub fn report_preview_scroll(&self, id: &str, sourcepos: SourcePos) -> bool {
+        let mut store = self.store.lock().unwrap();
+        if !store.filepath_map.contains_key(id) {
+            return false;
+        }
+
+        store.send(DocumentEvent::PreviewScrolled {
+            document_id: id.to_string(),
+            sourcepos,
+        });
+        true
     }
-}
 
-// API request/response structures
-#[derive(Facet)]
-pub struct CreateDocumentRequest {
-    pub filepath: String,
-}
+    /// Renders `content` in place of the file of document `id` until the file
+    /// is saved or the content is reverted, and refreshes open previews.
+    /// Returns false if the document is not registered.
+    pub fn set_unsaved_content(&self, id: &str, content: String) -> bool {
+        let mut store = self.store.lock().unwrap();
+        if !store.filepath_map.contains_key(id) {
+            return false;
+        }
+        store.unsaved_content.insert(id.to_string(), content);
+        store.mark_changed(id);
+        true
+    }
 
-#[derive(Facet)]
-pub struct CreateDocumentResponse {
-    pub id: String,
-}
+    /// Goes back to rendering the file of document `id` after
+    /// [`set_unsaved_content`](Self::set_unsaved_content). Returns false if the
+    /// document is not registered.
+    pub fn revert_unsaved_content(&self, id: &str) -> bool {
+        let mut store = self.store.lock().unwrap();
+        if !store.filepath_map.contains_key(id) {
+            return false;
+        }
+        if store.unsaved_content.remove(id).is_some() {
+            store.mark_changed(id);
+        }
+        true
+    }
 
-#[derive(Facet)]
-pub struct UpdatePositionRequest {
-    pub sourcepos: String,
-}
+    pub fn get_position(&self, id: &str) -> Option<EditorPosition> {
+        self.store.lock().unwrap().position_map.get(id).copied()
+    }
 
-pub fn create_app() -> Router {
-    create_app_with_state(AppState::new())
-}
+    pub fn get_document_root(&self, id: &str) -> Option<String> {
+        self.store.lock().unwrap().document_root(id)
+    }
 
-pub fn create_app_with_state(state: AppState) -> Router {
+    /// Records that document `id` references the asset or includes the file
+    /// at `path`, and watches it so that changes to it refresh the document.
+    pub fn track_dependency(&self, id: &str, path: &str) {
+        let needs_watch = {
+            let mut store = self.store.lock().unwrap();
+            let covered = store.is_in_watched_directory(FsPath::new(path));
+            let ids = store.dependency_map.entry(path.to_string()).or_default();
+            let newly_tracked = ids.is_empty();
+            ids.insert(id.to_string());
+            newly_tracked && !covered
+        };
+
+        if needs_watch && let Err(e) = self.watch_file(path) {
+            eprintln!("Failed to watch {}: {}", path, e);
+        }
+    }
+
+    /// Runs `f` for document `id` on the blocking thread pool. Async handlers
+    /// go through here for anything that may render, which reads files and
+    /// can wait for another render of the same document.
+    async fn run_blocking<T, F>(&self, id: &str, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(&AppState, &str) -> T + Send + 'static,
+    {
+        let state = self.clone();
+        let id = id.to_string();
+        tokio::task::spawn_blocking(move || f(&state, &id))
+            .await
+            .expect("blocking task panicked")
+    }
+
+    /// Returns the rendered blocks of a document, rendering it only if the file
+    /// changed since the last render.
+    ///
+    /// A render that the document changed during is returned but not cached,
+    /// as it may show the old content, so that the next request renders again.
+    pub fn render_document(
+        &self,
+        id: &str,
+    ) -> Result<Arc<Vec<markdown::RenderedBlock>>, RenderError> {
+        // Concurrent requests for a changed document wait for a single render,
+        // while other documents render alongside
+        let render_lock = {
+            let mut store = self.store.lock().unwrap();
+            if !store.filepath_map.contains_key(id) {
+                return Err(RenderError::DocumentNotFound);
+            }
+            store
+                .render_locks
+                .entry(id.to_string())
+                .or_default()
+                .clone()
+        };
+        let _render_guard = render_lock.lock().unwrap();
+
+        let (filepath, unsaved_content, generation) = {
+            let store = self.store.lock().unwrap();
+            if let Some(cached) = store.render_cache.get(id)
+                && cached.fresh
+            {
+                return Ok(cached.blocks.clone());
+            }
+            let filepath = store
+                .filepath_map
+                .get(id)
+                .cloned()
+                .ok_or(RenderError::DocumentNotFound)?;
+            (
+                filepath,
+                store.unsaved_content.get(id).cloned(),
+                store.render_generation(id),
+            )
+        };
+
+        let content = match unsaved_content.map_or_else(|| std::fs::read_to_string(&filepath), Ok) {
+            Ok(content) => content,
+            Err(e) => {
+                self.store.lock().unwrap().render_errors.insert(
+                    id.to_string(),
+                    format!("Failed to read {}: {}", filepath, e),
+                );
+                return Err(RenderError::ReadFailed);
+            }
+        };
+        let content_hash = utils::hash_content(&content);
+
+        {
+            let mut store = self.store.lock().unwrap();
+            store.render_errors.remove(id);
+            let current = store.render_generation(id) == generation;
+            if let Some(cached) = store.render_cache.get_mut(id)
+                && current
+                && cached.content_hash == content_hash
+            {
+                cached.fresh = true;
+                return Ok(cached.blocks.clone());
+            }
+        }
+
+        let front_matter = if self.get_document_extensions(id).front_matter {
+            front_matter::parse(&content)
+        } else {
+            None
+        };
+        let (document, error) = match try_render_markdown(self, id, &filepath, &content) {
+            Ok(document) => (document, None),
+            Err(_) => {
+                let message = "Error rendering markdown";
+                let document = markdown::RenderedDocument {
+                    blocks: vec![markdown::RenderedBlock::message(message)],
+                    outline: Vec::new(),
+                };
+                (document, Some(message.to_string()))
+            }
+        };
+        let blocks = Arc::new(document.blocks);
+
+        let mut store = self.store.lock().unwrap();
+        if store.filepath_map.contains_key(id) {
+            if let Some(error) = error {
+                store.render_errors.insert(id.to_string(), error);
+            }
+            if store.render_generation(id) != generation {
+                return Ok(blocks);
+            }
+            store.render_cache.insert(
+                id.to_string(),
+                CachedRender {
+                    content_hash,
+                    blocks: blocks.clone(),
+                    outline: Arc::new(document.outline),
+                    block_positions: blocks
+                        .iter()
+                        .flat_map(|block| sourcepos::block_positions_in_html(&block.html))
+                        .collect(),
+                    front_matter,
+                    fresh: true,
+                },
+            );
+        }
+
+        Ok(blocks)
+    }
+
+    /// Returns the headings of the last render of a document.
+    pub fn get_outline(&self, id: &str) -> Arc<Vec<markdown::Heading>> {
+        let store = self.store.lock().unwrap();
+        store
+            .render_cache
+            .get(id)
+            .map(|cached| cached.outline.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the front matter of the last render of a document.
+    pub fn get_front_matter(&self, id: &str) -> Option<front_matter::FrontMatter> {
+        let store = self.store.lock().unwrap();
+        store.render_cache.get(id)?.front_matter.clone()
+    }
+
+    /// Returns the front matter title of a document, falling back to its file
+    /// name.
+    fn document_title(&self, id: &str, filepath: &str) -> String {
+        self.get_front_matter(id)
+            .and_then(|front_matter| front_matter.title)
+            .unwrap_or_else(|| document_title(filepath).to_string())
+    }
+
+    /// Returns the current front matter of a document without rendering it,
+    /// reusing the cached render while it is fresh.
+    fn read_front_matter(&self, id: &str) -> Option<front_matter::FrontMatter> {
+        if !self.get_document_extensions(id).front_matter {
+            return None;
+        }
+        let (filepath, unsaved_content) = {
+            let store = self.store.lock().unwrap();
+            if let Some(cached) = store.render_cache.get(id)
+                && cached.fresh
+            {
+                return cached.front_matter.clone();
+            }
+            (
+                store.filepath_map.get(id)?.clone(),
+                store.unsaved_content.get(id).cloned(),
+            )
+        };
+        let content = unsaved_content.or_else(|| std::fs::read_to_string(filepath).ok())?;
+        front_matter::parse(&content)
+    }
+
+    /// Collects the metadata reported by the JSON document API.
+    pub fn get_document_info(&self, id: &str) -> Option<DocumentInfo> {
+        let front_matter = self.read_front_matter(id).unwrap_or_default();
+
+        let store = self.store.lock().unwrap();
+        let filepath = store.filepath_map.get(id)?.clone();
+        let position = store
+            .position_map
+            .get(id)
+            .copied()
+            .unwrap_or(EditorPosition::START);
+
+        let last_modified = std::fs::metadata(&filepath)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs());
+
+        Some(DocumentInfo {
+            id: id.to_string(),
+            title: front_matter
+                .title
+                .unwrap_or_else(|| document_title(&filepath).to_string()),
+            author: front_matter.author,
+            date: front_matter.date,
+            tags: front_matter.tags,
+            last_modified,
+            unsaved: store.unsaved_content.contains_key(id),
+            sourcepos: position.cursor.to_string(),
+            selection: position.selection.map(|selection| selection.to_string()),
+            subscriber_count: store.subscriber_map.get(id).copied().unwrap_or(0),
+            last_error: store.render_errors.get(id).cloned(),
+            filepath,
+        })
+    }
+
+    /// Subscribes to the events of document `id`, creating its channel if it
+    /// has no other subscribers.
+    fn subscribe(&self, id: &str) -> Subscription {
+        let rx = self
+            .store
+            .lock()
+            .unwrap()
+            .channels
+            .entry(id.to_string())
+            .or_insert_with(|| broadcast::channel(EVENT_CHANNEL_CAPACITY).0)
+            .subscribe();
+
+        Subscription {
+            rx,
+            store: self.store.clone(),
+            document_id: id.to_string(),
+        }
+    }
+
+    fn event_ids(&self, id: &str) -> EventIds {
+        let store = self.store.lock().unwrap();
+        store.event_ids.get(id).copied().unwrap_or_default()
+    }
+
+    /// Counts a connected SSE or WebSocket client until the returned guard is
+    /// dropped.
+    fn track_subscriber(&self, id: &str) -> SubscriberGuard {
+        *self
+            .store
+            .lock()
+            .unwrap()
+            .subscriber_map
+            .entry(id.to_string())
+            .or_insert(0) += 1;
+
+        SubscriberGuard {
+            store: self.store.clone(),
+            document_id: id.to_string(),
+        }
+    }
+
+    pub fn get_all_documents(&self) -> Vec<(String, String)> {
+        self.store
+            .lock()
+            .unwrap()
+            .filepath_map
+            .iter()
+            .map(|(id, filepath)| (id.clone(), filepath.clone()))
+            .collect()
+    }
+}
+
+/// Events a document's channel holds for a subscriber that has not received
+/// them yet. Subscribers that fall further behind skip to the current state.
+const EVENT_CHANNEL_CAPACITY: usize = 100;
+
+/// A receiver of one document's events. The document's channel is dropped
+/// with its last subscription.
+struct Subscription {
+    rx: broadcast::Receiver<SentEvent>,
+    store: Arc<Mutex<DocumentStore>>,
+    document_id: String,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Ok(mut store) = self.store.lock()
+            && store
+                .channels
+                .get(&self.document_id)
+                .is_some_and(|tx| tx.receiver_count() == 1)
+        {
+            store.channels.remove(&self.document_id);
+        }
+    }
+}
+
+struct SubscriberGuard {
+    store: Arc<Mutex<DocumentStore>>,
+    document_id: String,
+}
+
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        if let Ok(mut store) = self.store.lock()
+            && let Some(count) = store.subscriber_map.get_mut(&self.document_id)
+        {
+            *count -= 1;
+            if *count == 0 {
+                store.subscriber_map.remove(&self.document_id);
+            }
+        }
+    }
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// API request/response structures
+#[derive(Facet)]
+pub struct CreateDocumentRequest {
+    pub filepath: String,
+    /// Markdown extensions for this document, applied on top of the server's.
+    #[facet(default)]
+    pub extensions: Option<config::ExtensionSettings>,
+}
+
+#[derive(Facet)]
+pub struct CreateDocumentResponse {
+    pub id: String,
+}
+
+/// Body of `PUT /api/document/{id}/content`: the editor's unsaved buffer.
+#[derive(Facet)]
+pub struct UpdateContentRequest {
+    pub content: String,
+}
+
+/// Body of position updates and scroll reports. `sourcepos` and `selection`
+/// must be valid [`SourcePos`] ranges, such as `42:7-42:7`.
+#[derive(Facet)]
+pub struct UpdatePositionRequest {
+    pub sourcepos: String,
+    /// The range selected in the editor, if any.
+    #[facet(default)]
+    pub selection: Option<String>,
+}
+
+impl UpdatePositionRequest {
+    fn parse(body: &str) -> Result<EditorPosition, ApiError> {
+        let request: UpdatePositionRequest = parse_json_body(body)?;
+        let parse = |sourcepos: &str| {
+            sourcepos
+                .parse()
+                .map_err(|e: sourcepos::ParseSourcePosError| ApiError::bad_request(e.to_string()))
+        };
+
+        Ok(EditorPosition {
+            cursor: parse(&request.sourcepos)?,
+            selection: request.selection.as_deref().map(parse).transpose()?,
+        })
+    }
+}
+
+#[derive(Facet, Debug)]
+pub struct DocumentInfo {
+    pub id: String,
+    pub filepath: String,
+    /// The front matter title, or the file name.
+    pub title: String,
+    pub author: Option<String>,
+    pub date: Option<String>,
+    pub tags: Vec<String>,
+    /// Seconds since the Unix epoch, if the file exists.
+    pub last_modified: Option<u64>,
+    /// Whether the preview shows content pushed by an editor rather than the
+    /// file on disk.
+    pub unsaved: bool,
+    /// The editor's cursor, and its selection if it has one.
+    pub sourcepos: String,
+    pub selection: Option<String>,
+    pub subscriber_count: usize,
+    pub last_error: Option<String>,
+}
+
+#[derive(Facet, Debug)]
+pub struct DocumentListResponse {
+    pub documents: Vec<DocumentInfo>,
+}
+
+/// Body returned by the JSON API for every failed request.
+#[derive(Facet, Debug)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+pub fn create_app() -> Router {
+    create_app_with_state(AppState::new())
+}
+
+pub fn create_app_with_state(state: AppState) -> Router {
     Router::new()
         .route("/", get(list_documents))
+        .route("/api/documents", get(list_documents_json))
         .route("/api/document", post(create_document))
-        .route("/api/document/{id}", delete(delete_document))
+        .route(
+            "/api/document/{id}",
+            get(get_document_json).delete(delete_document),
+        )
         .route("/api/document/{id}/open", post(open_document))
         .route("/api/document/{id}/position", post(update_position))
+        .route("/api/document/{id}/scroll", post(report_preview_scroll))
+        .route(
+            "/api/document/{id}/content",
+            put(update_content).delete(revert_content),
+        )
+        .route("/api/document/{id}/events", get(editor_events))
+        .route("/assets/{name}", get(serve_vendor_asset))
         .route("/document/{id}", get(serve_document))
         .route("/document/{id}/updates", get(document_updates))
+        .route("/document/{id}/ws", get(document_socket))
+        .route("/document/{id}/assets/{*path}", get(serve_document_asset))
         .with_state(state)
 }
 
@@ -258,7 +1390,7 @@ async fn list_documents(
 ) -> impl IntoResponse {
     let documents = state.get_all_documents();
 
-    let mut html = format!(
+    let html = format!(
         r#"<!DOCTYPE html>
 <html lang="en">
 <head>
@@ -270,47 +1402,140 @@ async fn list_documents(
 <body>
 <main>
 <h1>Documents</h1>
-<ul>"#,
-        html_template::get_styles()
+{}
+</main>
+</body>
+</html>"#,
+        html_template::get_styles(),
+        html_template::render_document_tree(&documents)
     );
 
-    for (id, filepath) in documents {
-        html.push_str(&format!(
-            "<li><a href=\"/document/{}\">{}</a></li>\n",
-            id, filepath
-        ));
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, "text/html".parse().unwrap());
+
+    (StatusCode::OK, headers, html)
+}
+
+async fn list_documents_json(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    let mut documents = Vec::new();
+    for (id, _) in state.get_all_documents() {
+        documents.extend(
+            state
+                .run_blocking(&id, |state, id| state.get_document_info(id))
+                .await,
+        );
     }
+    documents.sort_by(|a, b| a.filepath.cmp(&b.filepath));
 
-    html.push_str("</ul>\n</main>\n</body>\n</html>");
+    json_response(
+        StatusCode::OK,
+        facet_json::to_string(&DocumentListResponse { documents }),
+    )
+}
 
+async fn get_document_json(
+    Path(id): Path<String>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    match state
+        .run_blocking(&id, |state, id| state.get_document_info(id))
+        .await
+    {
+        Some(info) => json_response(StatusCode::OK, facet_json::to_string(&info)),
+        None => ApiError::not_found("Document not found").into_response(),
+    }
+}
+
+fn json_response(status: StatusCode, body: String) -> axum::response::Response {
     let mut headers = HeaderMap::new();
-    headers.insert(header::CONTENT_TYPE, "text/html".parse().unwrap());
+    headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
 
-    (StatusCode::OK, headers, html)
+    (status, headers, body).into_response()
+}
+
+/// A failed API request, rendered as an [`ErrorResponse`] body.
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message: message.into(),
+        }
+    }
+
+    fn internal(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        let body = ErrorResponse {
+            error: self.message,
+        };
+        json_response(self.status, facet_json::to_string(&body))
+    }
+}
+
+fn parse_json_body<'a, T: Facet<'a>>(body: &'a str) -> Result<T, ApiError> {
+    facet_json::from_str(body)
+        .map_err(|e| ApiError::bad_request(format!("Invalid request body: {}", e)))
 }
 
 async fn create_document(
     axum::extract::State(state): axum::extract::State<AppState>,
     body: String,
-) -> impl IntoResponse {
-    // Parse the request body using facet
-    let request: CreateDocumentRequest =
-        facet_json::from_str(&body).unwrap_or_else(|_| CreateDocumentRequest {
-            filepath: "unknown".to_string(),
-        });
+) -> Result<axum::response::Response, ApiError> {
+    let request: CreateDocumentRequest = parse_json_body(&body)?;
 
     let filepath = request.filepath;
+    if filepath.trim().is_empty() {
+        return Err(ApiError::bad_request("filepath must not be empty"));
+    }
+
+    let extensions = request
+        .extensions
+        .map(|settings| settings.resolve(&state.default_extensions()))
+        .transpose()
+        .map_err(|e| ApiError::bad_request(e.to_string()))?;
 
     // Check if filepath already exists
     if let Some(existing_id) = state.get_id_by_filepath(&filepath) {
+        if let Some(extensions) = extensions {
+            state.set_document_extensions(&existing_id, extensions);
+        }
         let response = CreateDocumentResponse { id: existing_id };
+        return Ok(json_response(
+            StatusCode::CREATED,
+            facet_json::to_string(&response),
+        ));
+    }
 
-        let json_body = facet_json::to_string(&response);
-
-        let mut headers = HeaderMap::new();
-        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
-
-        return (StatusCode::CREATED, headers, json_body);
+    let path = FsPath::new(&filepath);
+    if path.is_dir() {
+        return Err(ApiError::bad_request(format!(
+            "{} is a directory, not a file",
+            filepath
+        )));
+    }
+    if !path.is_file() {
+        return Err(ApiError::not_found(format!("File not found: {}", filepath)));
     }
 
     // Generate new ID: consistent hash-based
@@ -318,55 +1543,122 @@ async fn create_document(
 
     // Store the document
     state.add_document(doc_id.clone(), filepath);
+    if let Some(extensions) = extensions {
+        state.set_document_extensions(&doc_id, extensions);
+    }
 
     let response = CreateDocumentResponse { id: doc_id };
-
-    let json_body = facet_json::to_string(&response);
-
-    let mut headers = HeaderMap::new();
-    headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
-
-    (StatusCode::CREATED, headers, json_body)
+    Ok(json_response(
+        StatusCode::CREATED,
+        facet_json::to_string(&response),
+    ))
 }
 
 async fn delete_document(
     Path(id): Path<String>,
     axum::extract::State(state): axum::extract::State<AppState>,
-) -> StatusCode {
+) -> Result<StatusCode, ApiError> {
     match state.remove_document(&id) {
-        Some(_) => StatusCode::OK,
-        None => StatusCode::NOT_FOUND,
+        Some(_) => Ok(StatusCode::OK),
+        None => Err(ApiError::not_found("Document not found")),
     }
 }
 
-async fn open_document(Path(id): Path<String>) -> impl IntoResponse {
-    println!("Opening document: {}", id);
+async fn open_document(
+    Path(id): Path<String>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    if state.get_filepath_by_id(&id).is_none() {
+        return Err(ApiError::not_found("Document not found"));
+    }
+
+    // The Host header is client controlled, so open the address we are bound to
+    let url = state.server_url(&format!("/document/{}", id));
+
+    println!("Opening document: {}", url);
+    state
+        .open_in_browser(&url)
+        .map_err(|e| ApiError::internal(format!("Failed to launch browser: {}", e)))?;
 
     let mut headers = HeaderMap::new();
     headers.insert(header::CONTENT_TYPE, "text/plain".parse().unwrap());
 
-    (StatusCode::CREATED, headers, "Document opened")
+    Ok((StatusCode::CREATED, headers, "Document opened"))
 }
 
 async fn update_position(
     Path(id): Path<String>,
     axum::extract::State(state): axum::extract::State<AppState>,
     body: String,
-) -> StatusCode {
-    // Parse the request body using facet
-    let request: UpdatePositionRequest =
-        facet_json::from_str(&body).unwrap_or_else(|_| UpdatePositionRequest {
-            sourcepos: "unknown".to_string(),
-        });
+) -> Result<StatusCode, ApiError> {
+    let position = UpdatePositionRequest::parse(&body)?;
 
     // Update position in store and broadcast event
-    state.update_position(&id, request.sourcepos.clone());
+    let updated = state
+        .run_blocking(&id, move |state, id| state.update_position(id, position))
+        .await;
+    if !updated {
+        return Err(ApiError::not_found("Document not found"));
+    }
 
-    println!(
-        "Updating position for document {}: {}",
-        id, request.sourcepos
-    );
-    StatusCode::CREATED
+    println!("Updating position for document {}: {}", id, position.cursor);
+    Ok(StatusCode::CREATED)
+}
+
+async fn update_content(
+    Path(id): Path<String>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+    body: String,
+) -> Result<StatusCode, ApiError> {
+    let request: UpdateContentRequest = parse_json_body(&body)?;
+
+    if !state.set_unsaved_content(&id, request.content) {
+        return Err(ApiError::not_found("Document not found"));
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn revert_content(
+    Path(id): Path<String>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Result<StatusCode, ApiError> {
+    if !state.revert_unsaved_content(&id) {
+        return Err(ApiError::not_found("Document not found"));
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn report_preview_scroll(
+    Path(id): Path<String>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+    body: String,
+) -> Result<StatusCode, ApiError> {
+    let sourcepos = UpdatePositionRequest::parse(&body)?.cursor;
+
+    if !state.report_preview_scroll(&id, sourcepos) {
+        return Err(ApiError::not_found("Document not found"));
+    }
+    Ok(StatusCode::CREATED)
+}
+
+async fn serve_vendor_asset(Path(name): Path<String>) -> impl IntoResponse {
+    let Some((content_type, content)) = html_template::vendor_asset(&name) else {
+        return (StatusCode::NOT_FOUND, "Asset not found").into_response();
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+    // Bundled assets only change with the binary
+    headers.insert(header::CACHE_CONTROL, "max-age=3600".parse().unwrap());
+
+    (StatusCode::OK, headers, content).into_response()
+}
+
+fn document_title(filepath: &str) -> &str {
+    FsPath::new(filepath)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("Markdown Document")
 }
 
 async fn serve_document(
@@ -381,99 +1673,378 @@ async fn serve_document(
         }
     };
 
-    // Try to read the file
-    let markdown_content = match std::fs::read_to_string(&filepath) {
-        Ok(content) => content,
-        Err(_) => {
+    // Render the markdown, or reuse the cached render
+    let rendered = state
+        .run_blocking(&id, |state, id| state.render_document(id))
+        .await;
+    let markdown_html = match rendered {
+        Ok(blocks) => blocks
+            .iter()
+            .map(|block| block.html.as_str())
+            .collect::<String>(),
+        Err(RenderError::DocumentNotFound) => {
+            return (StatusCode::NOT_FOUND, "Document not found").into_response();
+        }
+        Err(RenderError::ReadFailed) => {
             return (StatusCode::NOT_FOUND, "File not found").into_response();
         }
     };
 
-    // Try to render the markdown
-    let markdown_html = match try_render_markdown(&markdown_content) {
-        Ok(html) => html,
+    // Wrap in HTML template with the front matter title, or the file name
+    let title = state.document_title(&id, &filepath);
+
+    let outline = state.get_outline(&id);
+
+    let html_content = html_template::wrap_in_html_template(&markdown_html, Some(&title), &outline);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, "text/html".parse().unwrap());
+
+    (StatusCode::OK, headers, html_content).into_response()
+}
+
+async fn serve_document_asset(
+    Path((id, asset_path)): Path<(String, String)>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> impl IntoResponse {
+    let root = match state.get_document_root(&id) {
+        Some(root) => root,
+        None => {
+            return (StatusCode::NOT_FOUND, "Document not found").into_response();
+        }
+    };
+
+    let filepath = match tokio::fs::canonicalize(FsPath::new(&root).join(&asset_path)).await {
+        Ok(path) => path,
         Err(_) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to parse markdown",
-            )
-                .into_response();
+            return (StatusCode::NOT_FOUND, "Asset not found").into_response();
         }
     };
 
-    // Wrap in HTML template with document title based on filepath
-    let title = std::path::Path::new(&filepath)
-        .file_stem()
-        .and_then(|stem| stem.to_str())
-        .unwrap_or("Markdown Document");
+    // Never serve anything outside the document's root
+    let Ok(relative_path) = filepath.strip_prefix(&root) else {
+        return (StatusCode::FORBIDDEN, "Asset outside document root").into_response();
+    };
 
-    let html_content = html_template::wrap_in_html_template(&markdown_html, Some(title));
+    // Nor hidden files and directories such as .env or .git, which are skipped
+    // when looking for documents too
+    if utils::is_hidden(relative_path) {
+        return (StatusCode::FORBIDDEN, "Hidden files are not served").into_response();
+    }
+
+    let content = match tokio::fs::read(&filepath).await {
+        Ok(content) => content,
+        Err(_) => {
+            return (StatusCode::NOT_FOUND, "Asset not found").into_response();
+        }
+    };
+
+    let mime = mime_guess::from_path(&filepath).first_or_octet_stream();
 
     let mut headers = HeaderMap::new();
-    headers.insert(header::CONTENT_TYPE, "text/html".parse().unwrap());
+    headers.insert(header::CONTENT_TYPE, mime.as_ref().parse().unwrap());
+    headers.insert(header::CACHE_CONTROL, "no-cache".parse().unwrap());
 
-    (StatusCode::OK, headers, html_content).into_response()
+    (StatusCode::OK, headers, content).into_response()
+}
+
+/// Follows one document for a connected client, turning its events into the
+/// messages the client is sent.
+struct DocumentUpdates {
+    state: AppState,
+    id: String,
+    subscription: Subscription,
+    /// The blocks the client has rendered, which file changes are diffed
+    /// against.
+    rendered_blocks: Arc<Vec<markdown::RenderedBlock>>,
+    /// How the client is connected. Editors on a WebSocket also follow
+    /// preview scrolling.
+    transport: Transport,
+    // Counted as a subscriber for as long as the connection is open
+    _subscriber_guard: SubscriberGuard,
+}
+
+impl DocumentUpdates {
+    async fn new(state: &AppState, id: &str) -> Self {
+        Self {
+            state: state.clone(),
+            id: id.to_string(),
+            subscription: state.subscribe(id),
+            rendered_blocks: render_current_blocks(state, id).await,
+            transport: Transport::Sse,
+            _subscriber_guard: state.track_subscriber(id),
+        }
+    }
+
+    fn over_socket(mut self) -> Self {
+        self.transport = Transport::Socket;
+        self
+    }
+
+    /// Resumes an SSE stream whose client last saw event `last_event_id`.
+    /// Clients that missed a file change are sent the whole render.
+    fn resume_from(mut self, last_event_id: Option<u64>) -> Self {
+        if let Some(seen) = last_event_id
+            && self.state.event_ids(&self.id).missed_file_change(seen)
+        {
+            self.rendered_blocks = Arc::new(Vec::new());
+        }
+        self
+    }
+
+    /// The messages that bring the client up to date, sent when it connects
+    /// or falls behind: the current render if the client does not have it,
+    /// and the current position.
+    async fn current_state(&mut self) -> Vec<UpdateMessage> {
+        let id = self.state.event_ids(&self.id).last;
+        let mut messages: Vec<UpdateMessage> = self.file_changed().await.into_iter().collect();
+        messages.push(self.current_position().await);
+        messages
+            .into_iter()
+            .map(|message| message.with_id(id))
+            .collect()
+    }
+
+    async fn current_position(&self) -> UpdateMessage {
+        let position = self
+            .state
+            .run_blocking(&self.id, |state, id| {
+                let position = state.get_position(id).unwrap_or(EditorPosition::START);
+                state.resolve_position(id, position)
+            })
+            .await;
+        PositionResponse::message(self.transport, &position)
+    }
+
+    /// Waits for the messages of the next event. A client that fell behind
+    /// is sent the current state in place of the events it missed. Returns
+    /// `None` once the document's channel is closed.
+    async fn next(&mut self) -> Option<Vec<UpdateMessage>> {
+        let received = self.recv().await;
+        self.messages(received).await
+    }
+
+    /// Waits for the next event. Unlike [`DocumentUpdates::next`] this is
+    /// cancel safe, so it can be raced against requests from the client.
+    async fn recv(&mut self) -> Result<SentEvent, broadcast::error::RecvError> {
+        self.subscription.rx.recv().await
+    }
+
+    /// Returns the messages for what [`DocumentUpdates::recv`] received.
+    async fn messages(
+        &mut self,
+        received: Result<SentEvent, broadcast::error::RecvError>,
+    ) -> Option<Vec<UpdateMessage>> {
+        match received {
+            Ok(SentEvent { id, event }) => Some(
+                self.message(event)
+                    .await
+                    .map(|message| message.with_id(id))
+                    .into_iter()
+                    .collect(),
+            ),
+            Err(broadcast::error::RecvError::Lagged(_)) => Some(self.current_state().await),
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    }
+
+    /// Returns the message for `event`, if it concerns the client.
+    async fn message(&mut self, event: DocumentEvent) -> Option<UpdateMessage> {
+        match event {
+            DocumentEvent::FileChanged { .. } => self.file_changed().await,
+            DocumentEvent::PositionUpdate { position, .. } => {
+                Some(PositionResponse::message(self.transport, &position))
+            }
+            DocumentEvent::PreviewScrolled { sourcepos, .. } => {
+                let editor = self.transport == Transport::Socket;
+                editor.then(|| ScrollResponse::message(self.transport, sourcepos))
+            }
+            DocumentEvent::FileDeleted { filepath, .. } => {
+                Some(self.file_moved("file_deleted", filepath))
+            }
+            DocumentEvent::FileRenamed { filepath, .. } => {
+                Some(self.file_moved("file_renamed", filepath))
+            }
+        }
+    }
+
+    fn file_moved(&self, event: &'static str, filepath: String) -> UpdateMessage {
+        let response = FileMovedResponse {
+            title: self.state.document_title(&self.id, &filepath),
+            filepath,
+        };
+        UpdateMessage::new(self.transport, event, response)
+    }
+
+    /// Patches the client's blocks to the current render, unless they are
+    /// already up to date.
+    async fn file_changed(&mut self) -> Option<UpdateMessage> {
+        let blocks = render_current_blocks(&self.state, &self.id).await;
+        if Arc::ptr_eq(&blocks, &self.rendered_blocks) || blocks == self.rendered_blocks {
+            return None;
+        }
+
+        let response = FileChangedResponse {
+            blocks: patch::diff_blocks(&self.rendered_blocks, &blocks),
+            outline: self.state.get_outline(&self.id).to_vec(),
+        };
+        self.rendered_blocks = blocks;
+        Some(UpdateMessage::new(self.transport, "file_changed", response))
+    }
 }
 
 async fn document_updates(
     Path(id): Path<String>,
     axum::extract::State(state): axum::extract::State<AppState>,
+    request_headers: HeaderMap,
 ) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
     // Check if document exists
     if state.get_filepath_by_id(&id).is_none() {
         return Err(StatusCode::NOT_FOUND);
     }
 
-    // Get current position and send it immediately
-    let current_position = state
-        .get_position(&id)
-        .unwrap_or_else(|| "1:1-1:1".to_string());
+    // Set by browsers when they reconnect
+    let last_event_id = request_headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
 
-    // Subscribe to broadcast channel
-    let rx = state.event_tx.subscribe();
+    // Subscribe to the document's channel
+    let mut updates = DocumentUpdates::new(&state, &id)
+        .await
+        .resume_from(last_event_id);
 
     // Create stream that starts with current position and then listens for updates
     let stream = async_stream::stream! {
-        // Send current position immediately
-        yield Ok(Event::default()
-            .event("position")
-            .data(format!("{{\"sourcepos\":\"{}\"}}", current_position)));
+        // Send current position immediately, after the render if it was missed
+        for message in updates.current_state().await {
+            yield Ok(message.sse_event());
+        }
 
-        let mut rx = rx;
         // Listen for updates
-        while let Ok(event) = rx.recv().await {
-            match event {
-                DocumentEvent::FileChanged { document_id } if document_id == id => {
-                    let html_content = match state.get_filepath_by_id(&document_id) {
-                        Some(filepath) => {
-                            match std::fs::read_to_string(&filepath) {
-                                Ok(content) => {
-                                    match try_render_markdown(&content) {
-                                        Ok(html) => html,
-                                        Err(_) => String::from("<p>Error rendering markdown</p>"),
-                                    }
-                                },
-                                Err(_) => String::from("<p>Error reading file</p>"),
-                            }
-                        },
-                        None => String::from("<p>Document not found</p>"),
-                    };
-
-                    let response = FileChangedResponse { html: html_content };
-                    let json_data = facet_json::to_string(&response);
-
-                    yield Ok(Event::default()
-                        .event("file_changed")
-                        .data(json_data));
-                },
-                DocumentEvent::PositionUpdate { document_id, sourcepos } if document_id == id => {
-                    yield Ok(Event::default()
-                        .event("position")
-                        .data(format!("{{\"sourcepos\":\"{}\"}}", sourcepos)));
+        while let Some(messages) = updates.next().await {
+            for message in messages {
+                yield Ok(message.sse_event());
+            }
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(
+        axum::response::sse::KeepAlive::new()
+            .interval(Duration::from_secs(30))
+            .text("ping"),
+    ))
+}
+
+/// Envelope of messages sent by WebSocket clients. The rest of the message
+/// depends on `event`: an [`UpdatePositionRequest`] for `position`, an
+/// [`UpdateContentRequest`] for `content`, and nothing for `revert`.
+#[derive(Facet)]
+pub struct SocketRequest {
+    pub event: String,
+}
+
+async fn document_socket(
+    Path(id): Path<String>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+    upgrade: Result<ws::WebSocketUpgrade, ws::rejection::WebSocketUpgradeRejection>,
+) -> Result<axum::response::Response, ApiError> {
+    if state.get_filepath_by_id(&id).is_none() {
+        return Err(ApiError::not_found("Document not found"));
+    }
+    // Unknown documents are reported before requests that are not upgrades
+    let upgrade = match upgrade {
+        Ok(upgrade) => upgrade,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+    Ok(upgrade.on_upgrade(move |socket| serve_document_socket(socket, state, id)))
+}
+
+/// Sends a WebSocket client the events of the SSE stream, plus `scroll`
+/// events, and applies the positions and content it sends. Messages that
+/// cannot be applied are answered with an `error` event.
+async fn serve_document_socket(mut socket: ws::WebSocket, state: AppState, id: String) {
+    let mut updates = DocumentUpdates::new(&state, &id).await.over_socket();
+
+    for message in updates.current_state().await {
+        if socket.send(message.socket_message()).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        // Only the wait for an event is raced against the socket, so that a
+        // request arriving while an event is rendered does not drop it
+        let messages = tokio::select! {
+            received = updates.recv() => match updates.messages(received).await {
+                Some(messages) => messages,
+                None => break,
+            },
+            request = socket.recv() => match request {
+                Some(Ok(ws::Message::Text(text))) => match apply_socket_request(&state, &id, &text).await {
+                    Ok(()) => continue,
+                    Err(e) => {
+                        let response = ErrorResponse { error: e.message };
+                        vec![UpdateMessage::new(Transport::Socket, "error", response)]
+                    }
                 },
-                _ => {
-                    // Ignore events for other documents
+                Some(Ok(ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        for message in messages {
+            if socket.send(message.socket_message()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn apply_socket_request(state: &AppState, id: &str, text: &str) -> Result<(), ApiError> {
+    let request: SocketRequest = parse_json_body(text)?;
+    let applied = match request.event.as_str() {
+        "position" => {
+            let position = UpdatePositionRequest::parse(text)?;
+            state
+                .run_blocking(id, move |state, id| state.update_position(id, position))
+                .await
+        }
+        "content" => {
+            let request: UpdateContentRequest = parse_json_body(text)?;
+            state.set_unsaved_content(id, request.content)
+        }
+        "revert" => state.revert_unsaved_content(id),
+        event => return Err(ApiError::bad_request(format!("Unknown event: {}", event))),
+    };
+
+    if !applied {
+        return Err(ApiError::not_found("Document not found"));
+    }
+    Ok(())
+}
+
+/// SSE stream for editor plugins, reporting where previews of the document
+/// are scrolled to so that the editor can follow.
+async fn editor_events(
+    Path(id): Path<String>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    if state.get_filepath_by_id(&id).is_none() {
+        return Err(ApiError::not_found("Document not found"));
+    }
+
+    let mut subscription = state.subscribe(&id);
+    let stream = async_stream::stream! {
+        loop {
+            match subscription.rx.recv().await {
+                Ok(SentEvent { id, event: DocumentEvent::PreviewScrolled { sourcepos, .. } }) => {
+                    yield Ok(ScrollResponse::message(Transport::Sse, sourcepos).with_id(id).sse_event());
                 }
+                Ok(_) => {}
+                // Missed scroll reports are superseded by the next one
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
             }
         }
     };
@@ -485,9 +2056,384 @@ async fn document_updates(
     ))
 }
 
-fn try_render_markdown(content: &str) -> Result<String, ()> {
+/// Returns the document's current rendered blocks, substituting a message
+/// block when it cannot be rendered.
+async fn render_current_blocks(state: &AppState, id: &str) -> Arc<Vec<markdown::RenderedBlock>> {
+    state
+        .run_blocking(id, |state, id| state.render_document(id))
+        .await
+        .unwrap_or_else(|e| Arc::new(vec![markdown::RenderedBlock::message(e.message())]))
+}
+
+fn try_render_markdown(
+    state: &AppState,
+    id: &str,
+    filepath: &str,
+    content: &str,
+) -> Result<markdown::RenderedDocument, ()> {
+    // Assets and includes are tracked afresh on every render so that dropped
+    // references stop triggering refreshes
+    state.store.lock().unwrap().clear_dependencies(id);
+
     // For now, we'll assume the markdown module always succeeds
     // In a real implementation, you might want to add error handling
     // to the markdown::render_to_html function
-    Ok(markdown::render_to_html(content))
+    let resolver = DocumentLinkResolver {
+        state,
+        document_id: id,
+    };
+    Ok(markdown::render_document_to_blocks(
+        content,
+        FsPath::new(filepath),
+        &resolver,
+        &state.get_document_extensions(id),
+    ))
+}
+
+/// Points links between markdown files at their `/document/{id}` pages,
+/// registering linked files that are not watched yet, and images at the
+/// document's asset route. Assets and included files are tracked so that
+/// changes to them refresh the document.
+struct DocumentLinkResolver<'a> {
+    state: &'a AppState,
+    document_id: &'a str,
+}
+
+impl markdown::LinkResolver for DocumentLinkResolver<'_> {
+    fn document_url(&self, path: &FsPath) -> Option<String> {
+        if !path.is_file() {
+            return None;
+        }
+
+        let filepath = path.to_string_lossy().to_string();
+        let id = match self.state.get_id_by_filepath(&filepath) {
+            Some(id) => id,
+            None => {
+                let id = utils::generate_document_id(&filepath);
+                self.state.add_document(id.clone(), filepath);
+                id
+            }
+        };
+
+        Some(format!("/document/{}", id))
+    }
+
+    fn asset_url(&self, path: &FsPath) -> Option<String> {
+        let root = self.state.get_document_root(self.document_id)?;
+        let relative_path = path.strip_prefix(&root).ok()?;
+        let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+
+        self.state
+            .track_dependency(self.document_id, &path.to_string_lossy());
+
+        // The modification time busts the browser cache when the asset changes
+        let version = modified
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default();
+
+        Some(format!(
+            "/document/{}/assets/{}?v={}",
+            self.document_id,
+            relative_path.to_string_lossy(),
+            version
+        ))
+    }
+
+    fn include(&self, path: &FsPath) -> Option<String> {
+        // Only files the document's assets could be served from are included
+        let root = self.state.get_document_root(self.document_id)?;
+        if !utils::is_includable(FsPath::new(&root), path) {
+            return None;
+        }
+
+        let filepath = path.to_string_lossy().to_string();
+        // Unsaved content pushed for an included document is shown here too
+        let unsaved_content = {
+            let store = self.state.store.lock().unwrap();
+            store
+                .document_id_map
+                .get(&filepath)
+                .and_then(|id| store.unsaved_content.get(id))
+                .cloned()
+        };
+        let content = unsaved_content.or_else(|| std::fs::read_to_string(path).ok())?;
+
+        self.state.track_dependency(self.document_id, &filepath);
+        Some(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, content: &str) -> String {
+        let tmp_dir = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        let file_path = format!("{}/{}_{}.md", tmp_dir, name, std::process::id());
+        std::fs::write(&file_path, content).unwrap();
+        utils::to_absolute_path(&file_path)
+    }
+
+    #[test]
+    fn test_render_cache_is_shared_until_file_changes() {
+        let file_path = temp_file("test_render_cache", "# Cached");
+        let state = AppState::new();
+        let doc_id = utils::generate_document_id(&file_path);
+        state.add_document(doc_id.clone(), file_path.clone());
+
+        let first = state.render_document(&doc_id).unwrap();
+        let second = state.render_document(&doc_id).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // Without a watcher event the cached render is served as is
+        std::fs::write(&file_path, "# Updated").unwrap();
+        let cached = state.render_document(&doc_id).unwrap();
+        assert!(Arc::ptr_eq(&first, &cached));
+
+        // A watcher event makes the next request re-render once
+        state
+            .store
+            .lock()
+            .unwrap()
+            .handle_path_changes(&[FsPath::new(&file_path)]);
+        let updated = state.render_document(&doc_id).unwrap();
+        assert!(!Arc::ptr_eq(&first, &updated));
+        assert!(updated[0].html.contains("Updated"));
+        assert!(Arc::ptr_eq(
+            &updated,
+            &state.render_document(&doc_id).unwrap()
+        ));
+
+        let _ = std::fs::remove_file(&file_path);
+    }
+
+    #[test]
+    fn test_render_cache_skips_unchanged_content() {
+        let file_path = temp_file("test_render_cache_unchanged", "# Same");
+        let state = AppState::new();
+        let doc_id = utils::generate_document_id(&file_path);
+        state.add_document(doc_id.clone(), file_path.clone());
+
+        let first = state.render_document(&doc_id).unwrap();
+
+        // Saving identical content does not re-render
+        std::fs::write(&file_path, "# Same").unwrap();
+        state
+            .store
+            .lock()
+            .unwrap()
+            .handle_path_changes(&[FsPath::new(&file_path)]);
+        assert!(Arc::ptr_eq(
+            &first,
+            &state.render_document(&doc_id).unwrap()
+        ));
+
+        let _ = std::fs::remove_file(&file_path);
+    }
+
+    #[test]
+    fn test_render_document_errors() {
+        let state = AppState::new();
+        assert!(matches!(
+            state.render_document("missing-id"),
+            Err(RenderError::DocumentNotFound)
+        ));
+
+        state.add_document("gone-md-1".to_string(), "/nonexistent/gone.md".to_string());
+        assert!(matches!(
+            state.render_document("gone-md-1"),
+            Err(RenderError::ReadFailed)
+        ));
+    }
+
+    fn cursor_at(line: usize) -> EditorPosition {
+        EditorPosition {
+            cursor: format!("{}:1-{}:1", line, line).parse().unwrap(),
+            selection: None,
+        }
+    }
+
+    #[test]
+    fn test_channels_exist_while_subscribed() {
+        let file_path = temp_file("test_channels", "# One");
+        let other_path = temp_file("test_channels_other", "# Other");
+        let state = AppState::new();
+        let doc_id = utils::generate_document_id(&file_path);
+        let other_id = utils::generate_document_id(&other_path);
+        state.add_document(doc_id.clone(), file_path.clone());
+        state.add_document(other_id.clone(), other_path.clone());
+
+        // Events for documents nobody follows go nowhere
+        state.update_position(&doc_id, cursor_at(1));
+        assert!(state.store.lock().unwrap().channels.is_empty());
+
+        let mut first = state.subscribe(&doc_id);
+        let second = state.subscribe(&doc_id);
+        state.update_position(&other_id, cursor_at(1));
+        assert!(first.rx.try_recv().is_err());
+
+        drop(first);
+        assert!(state.store.lock().unwrap().channels.contains_key(&doc_id));
+        drop(second);
+        assert!(state.store.lock().unwrap().channels.is_empty());
+
+        let _ = std::fs::remove_file(&file_path);
+        let _ = std::fs::remove_file(&other_path);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_is_sent_current_state() {
+        let file_path = temp_file("test_lagging_subscriber", "# Before");
+        let state = AppState::new();
+        let doc_id = utils::generate_document_id(&file_path);
+        state.add_document(doc_id.clone(), file_path.clone());
+        let mut updates = DocumentUpdates::new(&state, &doc_id).await;
+
+        state.set_unsaved_content(&doc_id, "# After".to_string());
+        // Channels round their capacity up to a power of two
+        for line in 1..=EVENT_CHANNEL_CAPACITY * 2 {
+            state.update_position(&doc_id, cursor_at(line));
+        }
+
+        let messages = updates.next().await.unwrap();
+        let events: Vec<&str> = messages.iter().map(|message| message.event).collect();
+        assert_eq!(events, vec!["file_changed", "position"]);
+        assert!(messages[0].data.contains("After"));
+        let last_line = EVENT_CHANNEL_CAPACITY * 2;
+        assert!(messages[1]
+            .data
+            .contains(&format!(r#""cursor":"{0}:1-{0}:1""#, last_line)));
+
+        // Later events arrive one by one again
+        state.update_position(&doc_id, cursor_at(1));
+        let messages = updates.next().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].data.contains(r#""cursor":"1:1-1:1""#));
+
+        let _ = std::fs::remove_file(&file_path);
+    }
+
+    #[test]
+    fn test_watcher_follows_renames_and_deletes() {
+        let dir = std::env::temp_dir().join(format!("test_watcher_renames_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let old_path = dir.join("old.md");
+        let new_path = dir.join("new.md");
+        std::fs::write(&old_path, "# Moving").unwrap();
+
+        let state = AppState::new();
+        let old_filepath = old_path.to_string_lossy().to_string();
+        let doc_id = utils::generate_document_id(&old_filepath);
+        state.add_document(doc_id.clone(), old_filepath.clone());
+        state.render_document(&doc_id).unwrap();
+
+        // A file with the same content appearing next to it is a rename
+        std::fs::rename(&old_path, &new_path).unwrap();
+        let events = state
+            .store
+            .lock()
+            .unwrap()
+            .handle_path_changes(&[&old_path, &new_path]);
+        assert!(matches!(
+            &events[..],
+            [
+                DocumentEvent::FileRenamed { document_id, filepath },
+                DocumentEvent::FileChanged { .. },
+            ] if *document_id == doc_id && *filepath == new_path.to_string_lossy()
+        ));
+        assert_eq!(state.get_id_by_filepath(&old_filepath), None);
+        assert_eq!(
+            state.get_id_by_filepath(&new_path.to_string_lossy()),
+            Some(doc_id.clone())
+        );
+
+        // Deleted documents stay registered until the file reappears
+        std::fs::remove_file(&new_path).unwrap();
+        let events = state
+            .store
+            .lock()
+            .unwrap()
+            .handle_path_changes(&[&new_path]);
+        assert!(matches!(
+            &events[..],
+            [DocumentEvent::FileDeleted { document_id, .. }] if *document_id == doc_id
+        ));
+        assert!(state.get_filepath_by_id(&doc_id).is_some());
+
+        std::fs::write(&new_path, "# Back").unwrap();
+        let events = state
+            .store
+            .lock()
+            .unwrap()
+            .handle_path_changes(&[&new_path]);
+        assert!(matches!(
+            &events[..],
+            [DocumentEvent::FileChanged { document_id }] if *document_id == doc_id
+        ));
+        assert!(state.render_document(&doc_id).unwrap()[0]
+            .html
+            .contains("Back"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_watcher_follows_renames_of_unrendered_documents() {
+        let dir =
+            std::env::temp_dir().join(format!("test_unrendered_renames_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let old_path = dir.join("old.md");
+        let new_path = dir.join("new.md");
+        let other_path = dir.join("other.md");
+        std::fs::write(&old_path, "# Moving").unwrap();
+        std::fs::write(&other_path, "# Other").unwrap();
+
+        let state = AppState::new();
+        let doc_id = utils::generate_document_id(&old_path.to_string_lossy());
+        state.add_document(doc_id.clone(), old_path.to_string_lossy().to_string());
+        let other_id = utils::generate_document_id(&other_path.to_string_lossy());
+        state.add_document(other_id.clone(), other_path.to_string_lossy().to_string());
+
+        // Saved again, and reported twice in the batch, before ever rendering
+        std::fs::write(&old_path, "# Moved").unwrap();
+        let events =
+            state
+                .store
+                .lock()
+                .unwrap()
+                .handle_path_changes(&[&old_path, &other_path, &old_path]);
+        let changed = |id: &str| {
+            events
+                .iter()
+                .filter(|event| {
+                    matches!(event, DocumentEvent::FileChanged { document_id } if document_id == id)
+                })
+                .count()
+        };
+        assert_eq!(events.len(), 2);
+        assert_eq!(changed(&doc_id), 1);
+        assert_eq!(changed(&other_id), 1);
+
+        // Documents including it follow it to its new path
+        state.track_dependency(&other_id, &old_path.to_string_lossy());
+        std::fs::rename(&old_path, &new_path).unwrap();
+        let mut store = state.store.lock().unwrap();
+        let events = store.handle_path_changes(&[&old_path, &new_path]);
+        assert!(matches!(
+            &events[..],
+            [
+                DocumentEvent::FileRenamed { document_id, .. },
+                DocumentEvent::FileChanged { .. },
+                DocumentEvent::FileChanged { .. },
+            ] if *document_id == doc_id
+        ));
+        assert!(!store
+            .dependency_map
+            .contains_key(old_path.to_string_lossy().as_ref()));
+        assert!(store.dependency_map[new_path.to_string_lossy().as_ref()].contains(&other_id));
+        drop(store);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -1,21 +1,95 @@
-use clap::Parser;
-use livemarkdown::{create_app, create_app_with_state, utils, AppState};
+use clap::{Parser, Subcommand};
+use livemarkdown::config::{self, ConfigError, ExtensionSet, ExtensionSettings};
+use livemarkdown::{create_app_with_state, export, utils, AppState, DEFAULT_BROWSER_COMMAND};
+use std::net::SocketAddr;
 use std::process;
 use tokio::net::TcpListener;
 
 #[derive(Parser)]
 #[command(name = "livemarkdown")]
 #[command(about = "A markdown live preview server")]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(short = 'p', long = "port")]
     #[arg(help = "Port number to run the server on (defaults to 3030)")]
     #[arg(value_parser = validate_port)]
     port: Option<u16>,
 
-    #[arg(help = "Markdown file to serve")]
+    #[arg(long = "browser", default_value = DEFAULT_BROWSER_COMMAND)]
+    #[arg(help = "Command used to open documents in a browser")]
+    browser: String,
+
+    #[arg(long = "open")]
+    #[arg(help = "Open the preview in the browser once the server is running")]
+    open: bool,
+
+    #[command(flatten)]
+    markdown: MarkdownArgs,
+
+    #[arg(help = "Markdown file or directory of markdown files to serve")]
     file: Option<String>,
 }
 
+#[derive(Subcommand, Debug, PartialEq)]
+enum Command {
+    /// Render markdown to standalone HTML files without running the server
+    Export {
+        #[arg(help = "Markdown file or directory of markdown files to export")]
+        input: String,
+
+        #[arg(short = 'o', long = "output")]
+        #[arg(help = "Directory to write the HTML files to")]
+        output: String,
+
+        #[command(flatten)]
+        markdown: MarkdownArgs,
+    },
+}
+
+/// Options selecting the markdown extensions, on top of `livemarkdown.toml`.
+#[derive(clap::Args, Debug, Default, PartialEq)]
+struct MarkdownArgs {
+    #[arg(long = "config")]
+    #[arg(help = "Configuration file (defaults to ./livemarkdown.toml if present)")]
+    config: Option<String>,
+
+    #[arg(long = "preset")]
+    #[arg(help = "Extension preset to start from: default, gfm or commonmark")]
+    preset: Option<String>,
+
+    #[arg(long = "enable", value_name = "EXTENSION")]
+    #[arg(help = "Enable a markdown extension (repeatable)")]
+    enable: Vec<String>,
+
+    #[arg(long = "disable", value_name = "EXTENSION")]
+    #[arg(help = "Disable a markdown extension (repeatable)")]
+    disable: Vec<String>,
+}
+
+impl MarkdownArgs {
+    /// Resolves the configuration file and then the command line flags.
+    fn extensions(&self) -> Result<ExtensionSet, ConfigError> {
+        let file_settings = match &self.config {
+            Some(path) if !std::path::Path::new(path).is_file() => {
+                return Err(ConfigError::Invalid(format!("{} not found", path)));
+            }
+            Some(path) => ExtensionSettings::load(std::path::Path::new(path))?,
+            None => ExtensionSettings::load(std::path::Path::new(config::CONFIG_FILENAME))?,
+        };
+        let cli_settings = ExtensionSettings {
+            preset: self.preset.clone(),
+            enable: self.enable.clone(),
+            disable: self.disable.clone(),
+        };
+
+        let extensions = file_settings.resolve(&ExtensionSet::default())?;
+        cli_settings.resolve(&extensions)
+    }
+}
+
 fn validate_port(s: &str) -> Result<u16, String> {
     match s.parse::<u16>() {
         Ok(port) => {
@@ -33,39 +107,69 @@ fn validate_port(s: &str) -> Result<u16, String> {
 async fn main() {
     let args = Args::parse();
 
+    if let Some(Command::Export {
+        input,
+        output,
+        markdown,
+    }) = &args.command
+    {
+        run_export(input, output, &load_extensions(markdown));
+        return;
+    }
+
+    let extensions = load_extensions(&args.markdown);
+
     // Find an available port starting from 3030
     let port = find_available_port(args.port.unwrap_or(3030)).await;
 
-    let app = if let Some(filepath) = &args.file {
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+
+    let state = AppState::new()
+        .with_browser_command(&args.browser)
+        .with_extensions(extensions)
+        .with_server_addr(addr);
+    let mut preview_url = state.server_url("/");
+
+    if let Some(filepath) = &args.file {
         // Convert to absolute path for consistency
         let absolute_filepath = utils::to_absolute_path(filepath);
 
         // Check if file exists
-        if !std::path::Path::new(&absolute_filepath).exists() {
+        let path = std::path::Path::new(&absolute_filepath);
+        if !path.exists() {
             eprintln!("File not found: {}", filepath);
             process::exit(1);
         }
 
-        let state = AppState::new();
-        let doc_id = create_initial_document(&state, filepath.clone());
-
         println!("Starting livemarkdown server on port {}", port);
-        println!("Serving file: {}", filepath);
-        println!(
-            "Document URL: http://127.0.0.1:{}/document/{}",
-            port, doc_id
-        );
+        if path.is_dir() {
+            let doc_ids = state.add_directory(&absolute_filepath);
 
-        create_app_with_state(state)
+            println!("Serving directory: {}", filepath);
+            println!("Found {} markdown files", doc_ids.len());
+            println!("Index URL: {}", preview_url);
+        } else {
+            let doc_id = create_initial_document(&state, filepath.clone());
+
+            preview_url = state.server_url(&format!("/document/{}", doc_id));
+
+            println!("Serving file: {}", filepath);
+            println!("Document URL: {}", preview_url);
+        }
     } else {
         println!("Starting livemarkdown server on port {}", port);
-        create_app()
-    };
+    }
 
-    let addr = format!("127.0.0.1:{}", port);
+    let app = create_app_with_state(state.clone());
 
-    match TcpListener::bind(&addr).await {
+    match TcpListener::bind(addr).await {
         Ok(listener) => {
+            if args.open
+                && let Err(e) = state.open_in_browser(&preview_url)
+            {
+                eprintln!("Failed to open browser: {}", e);
+            }
+
             if let Err(e) = axum::serve(listener, app).await {
                 eprintln!("Server error: {}", e);
                 process::exit(1);
@@ -78,6 +182,32 @@ async fn main() {
     }
 }
 
+fn load_extensions(markdown: &MarkdownArgs) -> ExtensionSet {
+    markdown.extensions().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    })
+}
+
+fn run_export(input: &str, output: &str, extensions: &ExtensionSet) {
+    match export::export(
+        std::path::Path::new(input),
+        std::path::Path::new(output),
+        extensions,
+    ) {
+        Ok(written) => {
+            for path in &written {
+                println!("Wrote {}", path.display());
+            }
+            println!("Exported {} documents to {}", written.len(), output);
+        }
+        Err(e) => {
+            eprintln!("Export failed: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
 async fn find_available_port(start_port: u16) -> u16 {
     for port in start_port..start_port + 100 {
         let addr = format!("127.0.0.1:{}", port);
@@ -122,32 +252,124 @@ mod tests {
 
     #[test]
     fn test_args_parsing_with_port() {
-        let args = Args::try_parse_from(&["livemarkdown", "--port", "8080"]).unwrap();
+        let args = Args::try_parse_from(["livemarkdown", "--port", "8080"]).unwrap();
         assert_eq!(args.port, Some(8080));
         assert_eq!(args.file, None);
     }
 
     #[test]
     fn test_args_parsing_without_port() {
-        let args = Args::try_parse_from(&["livemarkdown"]).unwrap();
+        let args = Args::try_parse_from(["livemarkdown"]).unwrap();
         assert_eq!(args.port, None);
         assert_eq!(args.file, None);
     }
 
     #[test]
     fn test_args_parsing_with_file() {
-        let args = Args::try_parse_from(&["livemarkdown", "test.md"]).unwrap();
+        let args = Args::try_parse_from(["livemarkdown", "test.md"]).unwrap();
         assert_eq!(args.port, None);
         assert_eq!(args.file, Some("test.md".to_string()));
     }
 
     #[test]
     fn test_args_parsing_with_port_and_file() {
-        let args = Args::try_parse_from(&["livemarkdown", "--port", "3030", "test.md"]).unwrap();
+        let args = Args::try_parse_from(["livemarkdown", "--port", "3030", "test.md"]).unwrap();
         assert_eq!(args.port, Some(3030));
         assert_eq!(args.file, Some("test.md".to_string()));
     }
 
+    #[test]
+    fn test_args_parsing_browser_defaults() {
+        let args = Args::try_parse_from(["livemarkdown", "test.md"]).unwrap();
+        assert_eq!(args.browser, DEFAULT_BROWSER_COMMAND);
+        assert!(!args.open);
+    }
+
+    #[test]
+    fn test_args_parsing_with_browser_and_open() {
+        let args = Args::try_parse_from([
+            "livemarkdown",
+            "test.md",
+            "--open",
+            "--browser",
+            "firefox --new-window",
+        ])
+        .unwrap();
+        assert_eq!(args.browser, "firefox --new-window");
+        assert!(args.open);
+        assert_eq!(args.file, Some("test.md".to_string()));
+    }
+
+    #[test]
+    fn test_args_parsing_export() {
+        let args = Args::try_parse_from(["livemarkdown", "export", "docs", "-o", "out/"]).unwrap();
+        assert_eq!(args.file, None);
+        assert_eq!(
+            args.command,
+            Some(Command::Export {
+                input: "docs".to_string(),
+                output: "out/".to_string(),
+                markdown: MarkdownArgs::default(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_args_parsing_extensions() {
+        let args = Args::try_parse_from([
+            "livemarkdown",
+            "--preset",
+            "gfm",
+            "--enable",
+            "wikilinks",
+            "--enable",
+            "alerts",
+            "--disable",
+            "tagfilter",
+            "notes.md",
+        ])
+        .unwrap();
+        assert_eq!(args.markdown.preset, Some("gfm".to_string()));
+        assert_eq!(args.markdown.enable, vec!["wikilinks", "alerts"]);
+        assert_eq!(args.markdown.disable, vec!["tagfilter"]);
+
+        let extensions = args.markdown.extensions().unwrap();
+        assert!(extensions.wikilinks && extensions.alerts && extensions.table);
+        assert!(!extensions.tagfilter && !extensions.footnotes);
+    }
+
+    #[test]
+    fn test_args_parsing_export_extensions() {
+        let args = Args::try_parse_from([
+            "livemarkdown",
+            "export",
+            "docs",
+            "-o",
+            "out/",
+            "--preset",
+            "commonmark",
+        ])
+        .unwrap();
+        let Some(Command::Export { markdown, .. }) = args.command else {
+            panic!("expected export command");
+        };
+        assert_eq!(markdown.preset, Some("commonmark".to_string()));
+    }
+
+    #[test]
+    fn test_missing_config_file_is_an_error() {
+        let markdown = MarkdownArgs {
+            config: Some("/nonexistent/livemarkdown.toml".to_string()),
+            ..Default::default()
+        };
+        assert!(markdown.extensions().is_err());
+    }
+
+    #[test]
+    fn test_args_parsing_export_requires_output() {
+        assert!(Args::try_parse_from(["livemarkdown", "export", "docs"]).is_err());
+    }
+
     #[tokio::test]
     async fn test_find_available_port_with_free_port() {
         // Test with a high port number that's likely to be available
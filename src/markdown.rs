@@ -1,26 +1,589 @@
-use comrak::{markdown_to_html_with_plugins, ComrakOptions, ComrakPlugins};
+use crate::config::ExtensionSet;
+use crate::highlight::SyntaxHighlighter;
+use crate::{front_matter, html_template, utils};
+use comrak::adapters::{HeadingAdapter, HeadingMeta};
+use comrak::nodes::{AstNode, NodeValue, Sourcepos};
+use comrak::{
+    format_html_with_plugins, html, markdown_to_html_with_plugins, parse_document, Anchorizer,
+    Arena, ComrakOptions, ComrakPlugins, URLRewriter,
+};
+use facet::Facet;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::panic::RefUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
+/// Maps files referenced by relative links and images to the URLs they are served at.
+pub trait LinkResolver: Send + Sync {
+    /// Returns the URL for the markdown file at `path`, or `None` to leave the
+    /// link untouched.
+    fn document_url(&self, path: &Path) -> Option<String>;
+
+    /// Returns the URL for the image or other asset at `path`, or `None` to
+    /// leave the reference untouched.
+    fn asset_url(&self, _path: &Path) -> Option<String> {
+        None
+    }
+
+    /// Returns the markdown to render in place of an include directive naming
+    /// the file at `path`, or `None` to leave the directive untouched.
+    fn include(&self, path: &Path) -> Option<String> {
+        std::fs::read_to_string(path).ok()
+    }
+}
+
+/// Renders markdown with the default extension set.
 pub fn render_to_html(markdown_content: &str) -> String {
+    let mut options = default_options(&ExtensionSet::default());
+    detect_front_matter_delimiter(&mut options, markdown_content);
+    let plugins = default_plugins();
+
+    markdown_to_html_with_plugins(markdown_content, &options, &plugins)
+}
+
+/// A top-level block of a rendered document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RenderedBlock {
+    /// The `data-sourcepos` attribute of the block's root element, which
+    /// identifies the block in the page.
+    pub sourcepos: String,
+    pub html: String,
+}
+
+impl RenderedBlock {
+    /// Creates a block showing a plain message in place of rendered content.
+    pub fn message(text: &str) -> Self {
+        let sourcepos = "0:0-0:0".to_string();
+        let html = format!(
+            "<p data-sourcepos=\"{}\">{}</p>\n",
+            sourcepos,
+            utils::escape_html(text)
+        );
+        RenderedBlock { sourcepos, html }
+    }
+}
+
+/// A heading of a rendered document, for its table of contents.
+#[derive(Facet, Clone, Debug, PartialEq, Eq)]
+pub struct Heading {
+    pub level: u8,
+    /// The heading's text without markup.
+    pub text: String,
+    /// The `id` the heading was rendered with, if `header_ids` is enabled.
+    pub anchor: Option<String>,
+    pub sourcepos: String,
+}
+
+/// A document split into blocks, with the outline of its headings.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenderedDocument {
+    pub blocks: Vec<RenderedBlock>,
+    pub outline: Vec<Heading>,
+}
+
+/// Renders a markdown file with the given extensions, resolving relative
+/// `.md` links and image paths against the directory of `source_path` through
+/// `resolver`. Front matter is shown as a metadata header, and include
+/// directives are replaced with the files they name (see [`expand_includes`]).
+pub fn render_document_to_html(
+    markdown_content: &str,
+    source_path: &Path,
+    resolver: &dyn LinkResolver,
+    extensions: &ExtensionSet,
+) -> String {
+    let base_dir = source_path.parent().unwrap_or(Path::new(""));
+    let mut options = document_options(base_dir, resolver, extensions);
+    detect_front_matter_delimiter(&mut options, markdown_content);
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, markdown_content, &options);
+    let headings = HeadingIds::new(root, &options);
+    let plugins = document_plugins(&headings);
+    let mut including = vec![utils::to_absolute_path(&source_path.to_string_lossy())];
+    expand_includes(
+        root,
+        markdown_content,
+        resolver,
+        extensions,
+        &options,
+        &plugins,
+        &mut including,
+    );
+
+    let mut html = root
+        .first_child()
+        .and_then(metadata_block)
+        .map(|block| block.html)
+        .unwrap_or_default();
+    let mut output = Vec::new();
+    if format_html_with_plugins(root, &options, &mut output, &plugins).is_ok() {
+        html.push_str(&String::from_utf8_lossy(&output));
+    }
+    html
+}
+
+/// Renders a markdown file like [`render_document_to_html`], split into its
+/// top-level blocks.
+///
+/// Every block renders to exactly one element carrying a `data-sourcepos`
+/// attribute, so that the page can be patched block by block. Raw HTML blocks
+/// are wrapped in a `<div>`, front matter becomes a metadata header, included
+/// files become a single block each and footnote definitions are grouped into a
+/// single trailing block.
+pub fn render_document_to_blocks(
+    markdown_content: &str,
+    source_path: &Path,
+    resolver: &dyn LinkResolver,
+    extensions: &ExtensionSet,
+) -> RenderedDocument {
+    let base_dir = source_path.parent().unwrap_or(Path::new(""));
+    let mut options = document_options(base_dir, resolver, extensions);
+    detect_front_matter_delimiter(&mut options, markdown_content);
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, markdown_content, &options);
+    let headings = HeadingIds::new(root, &options);
+    let plugins = document_plugins(&headings);
+    let mut including = vec![utils::to_absolute_path(&source_path.to_string_lossy())];
+    expand_includes(
+        root,
+        markdown_content,
+        resolver,
+        extensions,
+        &options,
+        &plugins,
+        &mut including,
+    );
+
+    let mut blocks = Vec::new();
+    let mut outline = Vec::new();
+    let mut footnotes = Vec::new();
+    for node in root.children() {
+        let value = &node.data.borrow().value;
+        if matches!(value, NodeValue::FootnoteDefinition(_)) {
+            footnotes.push(node);
+        } else if matches!(value, NodeValue::FrontMatter(_)) {
+            blocks.extend(metadata_block(node));
+        } else {
+            blocks.extend(render_block(node, &options, &plugins));
+            collect_headings(node, &headings, &mut outline);
+        }
+    }
+
+    if !footnotes.is_empty() {
+        let section = arena.alloc(AstNode::from(NodeValue::Document));
+        for footnote in footnotes {
+            footnote.detach();
+            section.append(footnote);
+        }
+        blocks.extend(render_block(section, &options, &plugins));
+        collect_headings(section, &headings, &mut outline);
+    }
+
+    RenderedDocument { blocks, outline }
+}
+
+/// Replaces the include directives among the top-level blocks of `root`, the
+/// file at the top of `including`, with the files they name, rendered into a
+/// `<div class="include">`.
+///
+/// A directive is a paragraph of its own: `{{#include path}}`, with a path
+/// relative to the including file, or an embed `![[note]]` of a markdown file
+/// next to it. Included files are rendered without source positions, which
+/// would clash with those of the including document, and may include files
+/// themselves. Directives naming a file that `resolver` does not provide, or
+/// one that is already being included in `including`, are left as they are.
+fn expand_includes<'a>(
+    root: &'a AstNode<'a>,
+    markdown_content: &str,
+    resolver: &dyn LinkResolver,
+    extensions: &ExtensionSet,
+    options: &ComrakOptions,
+    plugins: &ComrakPlugins,
+    including: &mut Vec<String>,
+) {
+    let source_path = PathBuf::from(including.last().cloned().unwrap_or_default());
+    let base_dir = source_path.parent().unwrap_or(Path::new(""));
+    for node in root.children() {
+        let sourcepos = {
+            let ast = node.data.borrow();
+            if !matches!(ast.value, NodeValue::Paragraph)
+                || ast.sourcepos.start.line != ast.sourcepos.end.line
+            {
+                continue;
+            }
+            ast.sourcepos
+        };
+        let Some(target) = markdown_content
+            .lines()
+            .nth(sourcepos.start.line - 1)
+            .and_then(|line| include_target(line, base_dir))
+        else {
+            continue;
+        };
+        if including.contains(&target) {
+            continue;
+        }
+        let Some(content) = resolver.include(Path::new(&target)) else {
+            continue;
+        };
+
+        including.push(target);
+        let html = render_included(&content, resolver, extensions, plugins, including);
+        including.pop();
+
+        let attribute = if options.render.sourcepos {
+            format!(" data-sourcepos=\"{}\"", sourcepos)
+        } else {
+            String::new()
+        };
+        // The directive's text would be rendered after the raw HTML
+        for child in node.children().collect::<Vec<_>>() {
+            child.detach();
+        }
+        node.data.borrow_mut().value = NodeValue::Raw(format!(
+            "<div class=\"include\"{}>\n{}</div>\n",
+            attribute, html
+        ));
+    }
+}
+
+/// Renders the file at the top of `including` for [`expand_includes`].
+fn render_included(
+    markdown_content: &str,
+    resolver: &dyn LinkResolver,
+    extensions: &ExtensionSet,
+    plugins: &ComrakPlugins,
+    including: &mut Vec<String>,
+) -> String {
+    let source_path = PathBuf::from(including.last().cloned().unwrap_or_default());
+    let base_dir = source_path.parent().unwrap_or(Path::new(""));
+    let mut options = document_options(base_dir, resolver, extensions);
+    options.render.sourcepos = false;
+    detect_front_matter_delimiter(&mut options, markdown_content);
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, markdown_content, &options);
+    expand_includes(
+        root,
+        markdown_content,
+        resolver,
+        extensions,
+        &options,
+        plugins,
+        including,
+    );
+
+    let mut output = Vec::new();
+    if format_html_with_plugins(root, &options, &mut output, plugins).is_err() {
+        return String::new();
+    }
+    String::from_utf8_lossy(&output).to_string()
+}
+
+/// Returns the absolute path of the file an include directive on `line`
+/// names, if the line is one.
+fn include_target(line: &str, base_dir: &Path) -> Option<String> {
+    let line = line.trim();
+    let path = if let Some(path) = line
+        .strip_prefix("{{#include ")
+        .and_then(|rest| rest.strip_suffix("}}"))
+    {
+        PathBuf::from(path.trim())
+    } else if let Some(name) = line
+        .strip_prefix("![[")
+        .and_then(|rest| rest.strip_suffix("]]"))
+    {
+        // Embeds may name a heading or an alias after the note, and embed
+        // images as well as notes
+        let name = name.split(['#', '|']).next()?.trim();
+        let path = PathBuf::from(name);
+        let path = if path.extension().is_none() {
+            path.with_extension("md")
+        } else {
+            path
+        };
+        if !utils::is_markdown_file(&path) {
+            return None;
+        }
+        path
+    } else {
+        return None;
+    };
+
+    if path.as_os_str().is_empty() {
+        return None;
+    }
+    Some(utils::to_absolute_path(
+        &base_dir.join(path).to_string_lossy(),
+    ))
+}
+
+/// Appends the headings inside a block to `outline`, with the ids they were
+/// rendered with.
+fn collect_headings<'a>(block: &'a AstNode<'a>, headings: &HeadingIds, outline: &mut Vec<Heading>) {
+    for node in block.descendants() {
+        let ast = node.data.borrow();
+        let NodeValue::Heading(ref heading) = ast.value else {
+            continue;
+        };
+
+        let mut text = Vec::new();
+        html::collect_text(node, &mut text);
+        let sourcepos = ast.sourcepos.to_string();
+        outline.push(Heading {
+            level: heading.level,
+            text: String::from_utf8_lossy(&text).to_string(),
+            anchor: headings.by_sourcepos.get(&sourcepos).cloned(),
+            sourcepos,
+        });
+    }
+}
+
+/// Renders the headings of a document with ids that are unique across the
+/// whole document, although its blocks and included files are rendered
+/// separately.
+///
+/// The document's own headings are given their ids up front, in the order a
+/// single render of the document would give them. Headings of included files
+/// are disambiguated against those as they are rendered.
+struct HeadingIds {
+    /// The `header_ids` prefix, or `None` if headings get no ids.
+    prefix: Option<String>,
+    by_sourcepos: HashMap<String, String>,
+    anchorizer: Mutex<Anchorizer>,
+}
+
+impl HeadingIds {
+    fn new<'a>(root: &'a AstNode<'a>, options: &ComrakOptions) -> Self {
+        let prefix = options.extension.header_ids.clone();
+        let mut anchorizer = Anchorizer::new();
+        let mut by_sourcepos = HashMap::new();
+        if prefix.is_some() {
+            for node in root.descendants() {
+                let ast = node.data.borrow();
+                if let NodeValue::Heading(_) = ast.value {
+                    let mut text = Vec::new();
+                    html::collect_text(node, &mut text);
+                    let id = anchorizer.anchorize(String::from_utf8_lossy(&text).to_string());
+                    by_sourcepos.insert(ast.sourcepos.to_string(), id);
+                }
+            }
+        }
+
+        HeadingIds {
+            prefix,
+            by_sourcepos,
+            anchorizer: Mutex::new(anchorizer),
+        }
+    }
+}
+
+// Writes the same markup comrak writes for headings, taking the ids from above
+impl HeadingAdapter for HeadingIds {
+    fn enter(
+        &self,
+        output: &mut dyn Write,
+        heading: &HeadingMeta,
+        sourcepos: Option<Sourcepos>,
+    ) -> io::Result<()> {
+        write!(output, "<h{}", heading.level)?;
+        if let Some(sourcepos) = sourcepos
+            && sourcepos.start.line > 0
+        {
+            write!(output, " data-sourcepos=\"{}\"", sourcepos)?;
+        }
+        output.write_all(b">")?;
+
+        if let Some(ref prefix) = self.prefix {
+            let id = sourcepos
+                .and_then(|sourcepos| self.by_sourcepos.get(&sourcepos.to_string()).cloned())
+                .unwrap_or_else(|| {
+                    self.anchorizer
+                        .lock()
+                        .unwrap()
+                        .anchorize(heading.content.clone())
+                });
+            write!(
+                output,
+                "<a inert href=\"#{}\" aria-hidden=\"true\" class=\"anchor\" id=\"{}{}\"></a>",
+                id, prefix, id
+            )?;
+        }
+        Ok(())
+    }
+
+    fn exit(&self, output: &mut dyn Write, heading: &HeadingMeta) -> io::Result<()> {
+        writeln!(output, "</h{}>", heading.level)
+    }
+}
+
+fn render_block<'a>(
+    node: &'a AstNode<'a>,
+    options: &ComrakOptions,
+    plugins: &ComrakPlugins,
+) -> Option<RenderedBlock> {
+    let mut output = Vec::new();
+    format_html_with_plugins(node, options, &mut output, plugins).ok()?;
+    let html = String::from_utf8(output).ok()?;
+    if html.trim().is_empty() {
+        return None;
+    }
+
+    if let Some(sourcepos) = root_element_sourcepos(&html) {
+        return Some(RenderedBlock { sourcepos, html });
+    }
+
+    let sourcepos = node.data.borrow().sourcepos.to_string();
+    let html = format!("<div data-sourcepos=\"{}\">\n{}</div>\n", sourcepos, html);
+    Some(RenderedBlock { sourcepos, html })
+}
+
+/// Renders a front matter node as a metadata header, if it has any fields
+/// worth showing.
+fn metadata_block<'a>(node: &'a AstNode<'a>) -> Option<RenderedBlock> {
+    let ast = node.data.borrow();
+    let NodeValue::FrontMatter(ref literal) = ast.value else {
+        return None;
+    };
+
+    let front_matter = front_matter::parse(literal)?;
+    let sourcepos = ast.sourcepos.to_string();
+    let html = html_template::render_metadata_header(&front_matter, &sourcepos)?;
+    Some(RenderedBlock { sourcepos, html })
+}
+
+/// Returns the `data-sourcepos` attribute of the first tag in `html`.
+fn root_element_sourcepos(html: &str) -> Option<String> {
+    let html = html.trim_start();
+    if !html.starts_with('<') || html.starts_with("<!") {
+        return None;
+    }
+
+    let tag = &html[..html.find('>')?];
+    let start = tag.find("data-sourcepos=\"")? + "data-sourcepos=\"".len();
+    let end = start + tag[start..].find('"')?;
+    Some(tag[start..end].to_string())
+}
+
+fn document_options<'a>(
+    base_dir: &'a Path,
+    resolver: &'a dyn LinkResolver,
+    extensions: &ExtensionSet,
+) -> ComrakOptions<'a> {
+    let mut options = default_options(extensions);
+    options.extension.link_url_rewriter = Some(Arc::new(LinkRewriter { base_dir, resolver }));
+    options.extension.image_url_rewriter = Some(Arc::new(ImageRewriter { base_dir, resolver }));
+    options
+}
+
+/// Lets TOML (`+++`) front matter be recognised as well as YAML (`---`).
+fn detect_front_matter_delimiter(options: &mut ComrakOptions, markdown_content: &str) {
+    if options.extension.front_matter_delimiter.is_some()
+        && let Some(delimiter) = front_matter::delimiter(markdown_content)
+    {
+        options.extension.front_matter_delimiter = Some(delimiter.to_string());
+    }
+}
+
+fn default_plugins<'p>() -> ComrakPlugins<'p> {
+    let mut plugins = ComrakPlugins::default();
+    plugins.render.codefence_syntax_highlighter = Some(&SyntaxHighlighter);
+    plugins
+}
+
+fn document_plugins(headings: &HeadingIds) -> ComrakPlugins<'_> {
+    let mut plugins = default_plugins();
+    plugins.render.heading_adapter = Some(headings);
+    plugins
+}
+
+fn default_options<'c>(extensions: &ExtensionSet) -> ComrakOptions<'c> {
     let mut options = ComrakOptions::default();
 
     // Enable source position tracking
     options.render.sourcepos = true;
 
-    // Enable GitHub-flavored markdown extensions
-    options.extension.strikethrough = true;
-    options.extension.tagfilter = true;
-    options.extension.table = true;
-    options.extension.autolink = true;
-    options.extension.tasklist = true;
-    options.extension.superscript = true;
-    options.extension.header_ids = Some("".to_string());
-    options.extension.footnotes = true;
-    options.extension.description_lists = true;
-    options.extension.front_matter_delimiter = Some("---".to_string());
+    options.extension.strikethrough = extensions.strikethrough;
+    options.extension.tagfilter = extensions.tagfilter;
+    options.extension.table = extensions.table;
+    options.extension.autolink = extensions.autolink;
+    options.extension.tasklist = extensions.tasklist;
+    options.extension.superscript = extensions.superscript;
+    options.extension.header_ids = extensions.header_ids.then(String::new);
+    options.extension.footnotes = extensions.footnotes;
+    options.extension.description_lists = extensions.description_lists;
+    options.extension.front_matter_delimiter = extensions.front_matter.then(|| "---".to_string());
+    options.extension.math_dollars = extensions.math;
+    options.extension.wikilinks_title_after_pipe = extensions.wikilinks;
+    options.extension.alerts = extensions.alerts;
 
-    let plugins = ComrakPlugins::default();
+    options
+}
 
-    markdown_to_html_with_plugins(markdown_content, &options, &plugins)
+struct LinkRewriter<'a> {
+    base_dir: &'a Path,
+    resolver: &'a dyn LinkResolver,
+}
+
+// Rewriting never leaves shared state half-updated, so observing it after a
+// panic is harmless.
+impl RefUnwindSafe for LinkRewriter<'_> {}
+
+impl URLRewriter for LinkRewriter<'_> {
+    fn to_html(&self, url: &str) -> String {
+        let Some((path, suffix)) = split_relative_url(url) else {
+            return url.to_string();
+        };
+        if !utils::is_markdown_file(Path::new(&path)) {
+            return url.to_string();
+        }
+
+        let target = utils::to_absolute_path(&self.base_dir.join(path).to_string_lossy());
+        match self.resolver.document_url(Path::new(&target)) {
+            Some(document_url) => format!("{}{}", document_url, suffix),
+            None => url.to_string(),
+        }
+    }
+}
+
+struct ImageRewriter<'a> {
+    base_dir: &'a Path,
+    resolver: &'a dyn LinkResolver,
+}
+
+// See `LinkRewriter`.
+impl RefUnwindSafe for ImageRewriter<'_> {}
+
+impl URLRewriter for ImageRewriter<'_> {
+    fn to_html(&self, url: &str) -> String {
+        let Some((path, _)) = split_relative_url(url) else {
+            return url.to_string();
+        };
+
+        let target = utils::to_absolute_path(&self.base_dir.join(path).to_string_lossy());
+        self.resolver
+            .asset_url(Path::new(&target))
+            .unwrap_or_else(|| url.to_string())
+    }
+}
+
+/// Splits a relative URL into its decoded path and its `?query#fragment` suffix.
+///
+/// Returns `None` for absolute URLs, root-relative paths and same-page anchors.
+fn split_relative_url(url: &str) -> Option<(String, &str)> {
+    let path_end = url.find(['?', '#']).unwrap_or(url.len());
+    let (path, suffix) = url.split_at(path_end);
+
+    let has_scheme = path
+        .find(':')
+        .is_some_and(|colon| !path[..colon].contains('/'));
+    if path.is_empty() || path.starts_with('/') || has_scheme {
+        return None;
+    }
+
+    Some((utils::percent_decode(path), suffix))
 }
 
 #[cfg(test)]
@@ -68,4 +631,360 @@ mod tests {
         // Verify that sourcepos attributes are present
         assert!(html.contains("data-sourcepos"));
     }
+
+    struct ExampleResolver;
+
+    impl LinkResolver for ExampleResolver {
+        fn document_url(&self, path: &Path) -> Option<String> {
+            path.is_file().then(|| {
+                let name = path.file_stem().unwrap().to_string_lossy();
+                format!("/document/{}", name)
+            })
+        }
+    }
+
+    #[test]
+    fn test_relative_markdown_links_are_resolved() {
+        let source_path = std::env::current_dir().unwrap().join("examples/simple.md");
+        let markdown = "[complex](./complex.md#table-example) and [sub](../examples/complex.md)";
+        let html = render_document_to_html(
+            markdown,
+            &source_path,
+            &ExampleResolver,
+            &ExtensionSet::default(),
+        );
+
+        assert!(html.contains(r#"href="/document/complex#table-example""#));
+        assert!(html.contains(r#"href="/document/complex""#));
+    }
+
+    #[test]
+    fn test_relative_images_are_resolved() {
+        struct AssetResolver;
+
+        impl LinkResolver for AssetResolver {
+            fn document_url(&self, _path: &Path) -> Option<String> {
+                None
+            }
+
+            fn asset_url(&self, path: &Path) -> Option<String> {
+                let name = path.file_name().unwrap().to_string_lossy();
+                Some(format!("/assets/{}", name))
+            }
+        }
+
+        let source_path = std::env::current_dir().unwrap().join("examples/simple.md");
+        let markdown = "![local](./img/arch%20v2.png) ![remote](https://example.com/a.png)";
+        let html = render_document_to_html(
+            markdown,
+            &source_path,
+            &AssetResolver,
+            &ExtensionSet::default(),
+        );
+
+        assert!(html.contains(r#"src="/assets/arch%20v2.png""#));
+        assert!(html.contains(r#"src="https://example.com/a.png""#));
+    }
+
+    #[test]
+    fn test_blocks_are_keyed_by_sourcepos() {
+        let source_path = std::env::current_dir().unwrap().join("examples/complex.md");
+        let blocks = render_document_to_blocks(
+            COMPLEX_MARKDOWN,
+            &source_path,
+            &ExampleResolver,
+            &ExtensionSet::default(),
+        )
+        .blocks;
+
+        assert!(!blocks.is_empty());
+        for block in &blocks {
+            let expected = format!("data-sourcepos=\"{}\"", block.sourcepos);
+            assert!(block.html.trim_start().starts_with('<'));
+            assert!(block.html[..block.html.find('>').unwrap()].contains(&expected));
+        }
+        assert_eq!(blocks[0].sourcepos, "1:1-1:26");
+
+        // Footnote definitions end up in a single trailing block
+        let footnote_blocks: Vec<_> = blocks
+            .iter()
+            .filter(|block| block.html.contains("class=\"footnotes\""))
+            .collect();
+        assert_eq!(footnote_blocks.len(), 1);
+        assert!(blocks.last().unwrap().html.contains("</section>"));
+    }
+
+    #[test]
+    fn test_blocks_wrap_raw_html() {
+        let source_path = std::env::current_dir().unwrap().join("examples/simple.md");
+        let markdown = "---\ntitle: Test\n---\n\n<div>raw</div>\n\nText";
+        let blocks = render_document_to_blocks(
+            markdown,
+            &source_path,
+            &ExampleResolver,
+            &ExtensionSet::default(),
+        )
+        .blocks;
+
+        // The front matter renders nothing and is skipped
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].sourcepos, "5:1-5:14");
+        assert!(blocks[0]
+            .html
+            .starts_with("<div data-sourcepos=\"5:1-5:14\">"));
+        assert_eq!(blocks[1].sourcepos, "7:1-7:4");
+    }
+
+    #[test]
+    fn test_outline_lists_headings() {
+        let source_path = std::env::current_dir().unwrap().join("examples/simple.md");
+        let markdown = "# Intro\n\nText\n\n## The `code` part\n\n> ### Quoted\n";
+        let document = render_document_to_blocks(
+            markdown,
+            &source_path,
+            &ExampleResolver,
+            &ExtensionSet::default(),
+        );
+
+        assert_eq!(
+            document.outline,
+            vec![
+                Heading {
+                    level: 1,
+                    text: "Intro".to_string(),
+                    anchor: Some("intro".to_string()),
+                    sourcepos: "1:1-1:7".to_string(),
+                },
+                Heading {
+                    level: 2,
+                    text: "The code part".to_string(),
+                    anchor: Some("the-code-part".to_string()),
+                    sourcepos: "5:1-5:18".to_string(),
+                },
+                Heading {
+                    level: 3,
+                    text: "Quoted".to_string(),
+                    anchor: Some("quoted".to_string()),
+                    sourcepos: "7:3-7:12".to_string(),
+                },
+            ]
+        );
+        for heading in &document.outline {
+            let anchor = heading.anchor.as_deref().unwrap();
+            assert!(document
+                .blocks
+                .iter()
+                .any(|block| block.html.contains(&format!("id=\"{}\"", anchor))));
+        }
+
+        let document = render_document_to_blocks(
+            markdown,
+            &source_path,
+            &ExampleResolver,
+            &ExtensionSet::gfm(),
+        );
+        assert_eq!(document.outline.len(), 3);
+        assert!(document
+            .outline
+            .iter()
+            .all(|heading| heading.anchor.is_none()));
+    }
+
+    #[test]
+    fn test_duplicate_headings_get_unique_ids() {
+        let source_path = std::env::current_dir().unwrap().join("examples/simple.md");
+        let markdown = "## Example\n\nText\n\n## Example\n\n> ## Example\n";
+        let document = render_document_to_blocks(
+            markdown,
+            &source_path,
+            &ExampleResolver,
+            &ExtensionSet::default(),
+        );
+
+        let anchors = ["example", "example-1", "example-2"];
+        let heading_blocks = document
+            .blocks
+            .iter()
+            .filter(|block| block.html.contains("Example"));
+        for (block, anchor) in heading_blocks.zip(anchors) {
+            assert!(block.html.contains(&format!(
+                "<a inert href=\"#{}\" aria-hidden=\"true\" class=\"anchor\" id=\"{}\"></a>Example",
+                anchor, anchor
+            )));
+        }
+        let outline_anchors: Vec<_> = document
+            .outline
+            .iter()
+            .map(|heading| heading.anchor.as_deref().unwrap())
+            .collect();
+        assert_eq!(outline_anchors, anchors);
+
+        // The same ids as when rendering the document as a whole
+        let html = render_document_to_html(
+            markdown,
+            &source_path,
+            &ExampleResolver,
+            &ExtensionSet::default(),
+        );
+        for anchor in anchors {
+            assert_eq!(html.matches(&format!("id=\"{}\"", anchor)).count(), 1);
+        }
+
+        // Apart from the ids, headings are written as comrak writes them
+        let markdown = "# Title\n\n## Usage\n";
+        assert_eq!(
+            render_document_to_html(
+                markdown,
+                &source_path,
+                &ExampleResolver,
+                &ExtensionSet::default(),
+            ),
+            render_to_html(markdown)
+        );
+    }
+
+    #[test]
+    fn test_front_matter_becomes_metadata_header() {
+        let source_path = std::env::current_dir().unwrap().join("examples/simple.md");
+        let markdown =
+            "+++\ntitle = \"Notes\"\nauthor = \"Ada\"\ntags = [\"rfc\"]\n+++\n\n# Notes\n";
+        let blocks = render_document_to_blocks(
+            markdown,
+            &source_path,
+            &ExampleResolver,
+            &ExtensionSet::default(),
+        )
+        .blocks;
+
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks[0].html.starts_with(&format!(
+            "<header class=\"document-metadata\" data-sourcepos=\"{}\">",
+            blocks[0].sourcepos
+        )));
+        assert!(blocks[0].html.contains("Ada"));
+        assert!(blocks[0].html.contains("<li>rfc</li>"));
+        assert!(!blocks[0].html.contains("Notes"));
+        assert_eq!(blocks[1].sourcepos, "7:1-7:7");
+
+        let html = render_document_to_html(
+            markdown,
+            &source_path,
+            &ExampleResolver,
+            &ExtensionSet::default(),
+        );
+        assert!(html.starts_with(&blocks[0].html));
+        assert!(!html.contains("title ="));
+    }
+
+    #[test]
+    fn test_code_blocks_are_highlighted() {
+        let html = render_to_html("Text\n\n```rust\nlet x = 1;\n```\n");
+
+        assert!(html.contains(
+            "<pre data-sourcepos=\"3:1-5:3\"><code class=\"language-rust\"><span class=\"hl-"
+        ));
+    }
+
+    #[test]
+    fn test_math_is_marked_for_typesetting() {
+        let html = render_to_html("Inline $x^2$ and\n\n$$\n\\sum_i i\n$$\n");
+
+        assert!(
+            html.contains(r#"<span data-math-style="inline" data-sourcepos="1:8-1:12">x^2</span>"#)
+        );
+        assert!(html.contains(r#"<span data-math-style="display" data-sourcepos="3:1-5:2">"#));
+        assert!(html.contains("\\sum_i i"));
+    }
+
+    #[test]
+    fn test_extension_set_selects_syntax() {
+        let source_path = std::env::current_dir().unwrap().join("examples/simple.md");
+        let markdown = "| a |\n|---|\n| 1 |\n\n> [!NOTE]\n> Careful\n\n[[Other page]]";
+
+        let html = render_document_to_html(
+            markdown,
+            &source_path,
+            &ExampleResolver,
+            &ExtensionSet::commonmark(),
+        );
+        assert!(!html.contains("<table"));
+        assert!(!html.contains("markdown-alert"));
+        assert!(html.contains("[[Other page]]"));
+
+        let mut extensions = ExtensionSet::gfm();
+        extensions.alerts = true;
+        extensions.wikilinks = true;
+        let html = render_document_to_html(markdown, &source_path, &ExampleResolver, &extensions);
+        assert!(html.contains("<table"));
+        assert!(html.contains("markdown-alert-note"));
+        assert!(html.contains(r#"href="Other%20page""#));
+    }
+
+    #[test]
+    fn test_include_directives_are_expanded() {
+        struct IncludeResolver;
+
+        impl LinkResolver for IncludeResolver {
+            fn document_url(&self, _path: &Path) -> Option<String> {
+                None
+            }
+
+            fn include(&self, path: &Path) -> Option<String> {
+                match path.file_name()?.to_str()? {
+                    "api.md" => Some("## API\n\n![[note|Note]]\n".to_string()),
+                    // Includes the document including it
+                    "note.md" => Some("A note\n\n![[simple]]\n".to_string()),
+                    _ => None,
+                }
+            }
+        }
+
+        let source_path = std::env::current_dir().unwrap().join("examples/simple.md");
+        let markdown = "# Doc\n\n{{#include ./snippets/api.md}}\n\n{{#include ./missing.md}}\n";
+        let blocks = render_document_to_blocks(
+            markdown,
+            &source_path,
+            &IncludeResolver,
+            &ExtensionSet::default(),
+        )
+        .blocks;
+
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[1].sourcepos, "3:1-3:30");
+        assert!(blocks[1]
+            .html
+            .starts_with("<div class=\"include\" data-sourcepos=\"3:1-3:30\">\n<h2>"));
+        assert_eq!(blocks[1].html.matches("data-sourcepos").count(), 1);
+        assert!(blocks[1]
+            .html
+            .contains("<div class=\"include\">\n<p>A note</p>\n<p>![[simple]]</p>\n</div>"));
+        assert!(blocks[2].html.contains("{{#include ./missing.md}}"));
+
+        let html = render_document_to_html(
+            markdown,
+            &source_path,
+            &IncludeResolver,
+            &ExtensionSet::default(),
+        );
+        assert!(html.contains(&blocks[1].html));
+    }
+
+    #[test]
+    fn test_other_links_are_left_untouched() {
+        let source_path = std::env::current_dir().unwrap().join("examples/simple.md");
+        let markdown = "[web](https://example.com/a.md) [root](/a.md) [anchor](#features) \
+                        [text](./notes.txt) [missing](./missing.md)";
+        let html = render_document_to_html(
+            markdown,
+            &source_path,
+            &ExampleResolver,
+            &ExtensionSet::default(),
+        );
+
+        assert!(html.contains(r#"href="https://example.com/a.md""#));
+        assert!(html.contains(r#"href="/a.md""#));
+        assert!(html.contains(r##"href="#features""##));
+        assert!(html.contains(r#"href="./notes.txt""#));
+        assert!(html.contains(r#"href="./missing.md""#));
+    }
 }
@@ -0,0 +1,104 @@
+use crate::markdown::RenderedBlock;
+use facet::Facet;
+use std::collections::HashMap;
+
+/// One block of a document patch, in page order.
+///
+/// Blocks the client already shows are sent by their `sourcepos` key alone;
+/// new or changed blocks also carry their HTML.
+#[derive(Facet, Debug, PartialEq)]
+pub struct BlockPatch {
+    pub sourcepos: String,
+    pub html: Option<String>,
+}
+
+/// Describes `current` in terms of the blocks already rendered from `previous`.
+///
+/// Blocks missing from the result are removed by the client.
+pub fn diff_blocks(previous: &[RenderedBlock], current: &[RenderedBlock]) -> Vec<BlockPatch> {
+    let previous: HashMap<&str, &str> = previous
+        .iter()
+        .map(|block| (block.sourcepos.as_str(), block.html.as_str()))
+        .collect();
+
+    current
+        .iter()
+        .map(|block| {
+            let unchanged = previous.get(block.sourcepos.as_str()) == Some(&block.html.as_str());
+            BlockPatch {
+                sourcepos: block.sourcepos.clone(),
+                html: (!unchanged).then(|| block.html.clone()),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(sourcepos: &str, html: &str) -> RenderedBlock {
+        RenderedBlock {
+            sourcepos: sourcepos.to_string(),
+            html: html.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_unchanged_blocks_are_sent_by_key() {
+        let previous = vec![block("1:1-1:5", "<h1>A</h1>"), block("3:1-3:5", "<p>B</p>")];
+        let current = vec![block("1:1-1:5", "<h1>A</h1>"), block("3:1-3:5", "<p>C</p>")];
+
+        let patch = diff_blocks(&previous, &current);
+        assert_eq!(
+            patch,
+            vec![
+                BlockPatch {
+                    sourcepos: "1:1-1:5".to_string(),
+                    html: None,
+                },
+                BlockPatch {
+                    sourcepos: "3:1-3:5".to_string(),
+                    html: Some("<p>C</p>".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_removed_and_added_blocks() {
+        let previous = vec![block("1:1-1:5", "<h1>A</h1>"), block("3:1-3:5", "<p>B</p>")];
+        let current = vec![
+            block("1:1-1:5", "<h1>A</h1>"),
+            block("3:1-4:5", "<p>B\nD</p>"),
+        ];
+
+        let patch = diff_blocks(&previous, &current);
+        assert_eq!(patch.len(), 2);
+        assert_eq!(patch[0].html, None);
+        assert_eq!(patch[1].sourcepos, "3:1-4:5");
+        assert_eq!(patch[1].html.as_deref(), Some("<p>B\nD</p>"));
+    }
+
+    #[test]
+    fn test_patch_from_nothing_carries_all_html() {
+        let current = vec![block("1:1-1:5", "<h1>A</h1>"), block("3:1-3:5", "<p>B</p>")];
+
+        let patch = diff_blocks(&[], &current);
+        assert!(patch.iter().all(|block| block.html.is_some()));
+    }
+
+    #[test]
+    fn test_patch_serialization() {
+        let patch = diff_blocks(&[], &[block("1:1-1:5", "<h1>\"A\"</h1>")]);
+        let json = facet_json::to_string(&patch[0]);
+        assert_eq!(json, r#"{"sourcepos":"1:1-1:5","html":"<h1>\"A\"</h1>"}"#);
+
+        let patch = diff_blocks(
+            &[block("1:1-1:5", "<h1>A</h1>")],
+            &[block("1:1-1:5", "<h1>A</h1>")],
+        );
+        let json = facet_json::to_string(&patch[0]);
+        assert_eq!(json, r#"{"sourcepos":"1:1-1:5","html":null}"#);
+    }
+}
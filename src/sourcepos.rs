@@ -0,0 +1,340 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A 1-based line and column in a markdown source file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineColumn {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A range of a markdown source file, in the `start_line:start_column-
+/// end_line:end_column` format of comrak's `data-sourcepos` attributes.
+///
+/// Ranges are ordered by their start, then by their end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourcePos {
+    pub start: LineColumn,
+    pub end: LineColumn,
+}
+
+impl SourcePos {
+    /// The first character of a document, where previews start.
+    pub const START: SourcePos = SourcePos {
+        start: LineColumn { line: 1, column: 1 },
+        end: LineColumn { line: 1, column: 1 },
+    };
+
+    /// Returns whether `point` lies within the range, including its ends.
+    pub fn contains(&self, point: LineColumn) -> bool {
+        self.start <= point && point <= self.end
+    }
+
+    /// Returns whether `other` lies entirely within the range.
+    pub fn encloses(&self, other: &SourcePos) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
+    /// Returns whether the two ranges share at least one position.
+    pub fn overlaps(&self, other: &SourcePos) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    /// Number of lines between the range and `line`, or 0 if it spans it.
+    fn line_distance(&self, line: usize) -> usize {
+        if line < self.start.line {
+            self.start.line - line
+        } else {
+            line.saturating_sub(self.end.line)
+        }
+    }
+}
+
+/// Where an editor's cursor is, and the range it has selected, if any.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EditorPosition {
+    pub cursor: SourcePos,
+    pub selection: Option<SourcePos>,
+}
+
+impl EditorPosition {
+    pub const START: EditorPosition = EditorPosition {
+        cursor: SourcePos::START,
+        selection: None,
+    };
+}
+
+impl fmt::Display for SourcePos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}-{}:{}",
+            self.start.line, self.start.column, self.end.line, self.end.column
+        )
+    }
+}
+
+/// Why a string is not a valid [`SourcePos`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseSourcePosError {
+    /// Not in the `line:column-line:column` format.
+    Syntax(String),
+    /// A start line or column of 0, or a range ending before it starts.
+    OutOfRange(String),
+}
+
+impl fmt::Display for ParseSourcePosError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseSourcePosError::Syntax(value) => write!(
+                f,
+                "invalid sourcepos \"{}\" (expected line:column-line:column)",
+                value
+            ),
+            ParseSourcePosError::OutOfRange(value) => write!(
+                f,
+                "invalid sourcepos \"{}\" (lines and columns start at 1 and the range must not end before it starts)",
+                value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseSourcePosError {}
+
+impl FromStr for SourcePos {
+    type Err = ParseSourcePosError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseSourcePosError::Syntax(s.to_string());
+        let parse_point = |point: &str| -> Result<LineColumn, ParseSourcePosError> {
+            let (line, column) = point.split_once(':').ok_or_else(error)?;
+            Ok(LineColumn {
+                line: line.parse().map_err(|_| error())?,
+                column: column.parse().map_err(|_| error())?,
+            })
+        };
+
+        let (start, end) = s.trim().split_once('-').ok_or_else(error)?;
+        let sourcepos = SourcePos {
+            start: parse_point(start)?,
+            end: parse_point(end)?,
+        };
+
+        // comrak ends blocks that take up a trailing line ending at column 0
+        // of the next line, so only the end column may be 0
+        if sourcepos.start.line == 0
+            || sourcepos.start.column == 0
+            || sourcepos.end < sourcepos.start
+        {
+            return Err(ParseSourcePosError::OutOfRange(s.to_string()));
+        }
+        Ok(sourcepos)
+    }
+}
+
+/// Elements that hold a block of the document, as opposed to inline spans
+/// such as `<em>` or `<code>` that comrak gives a `data-sourcepos` too.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "blockquote",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+/// Returns the ranges of the `data-sourcepos` attributes of block elements in
+/// `html`, in document order. Attributes that do not parse are skipped.
+pub fn block_positions_in_html(html: &str) -> impl Iterator<Item = SourcePos> + '_ {
+    const ATTRIBUTE: &str = "data-sourcepos=\"";
+
+    html.match_indices(ATTRIBUTE).filter_map(move |(index, _)| {
+        let tag = &html[html[..index].rfind('<')? + 1..index];
+        let name = tag.split_whitespace().next()?;
+        if !BLOCK_ELEMENTS.contains(&name) {
+            return None;
+        }
+        let value = &html[index + ATTRIBUTE.len()..];
+        value[..value.find('"')?].parse().ok()
+    })
+}
+
+/// Picks the rendered element that best matches `position`, an editor cursor
+/// or selection that rarely equals an element's range exactly.
+///
+/// The start of `position` is matched against `candidates`: the innermost range
+/// containing it wins, otherwise the nearest range by line, preferring inner
+/// ranges and then earlier ones on ties.
+pub fn resolve(position: SourcePos, candidates: &[SourcePos]) -> Option<SourcePos> {
+    let point = position.start;
+
+    let innermost = |best: SourcePos, candidate: SourcePos| {
+        if best.encloses(&candidate) && best != candidate {
+            candidate
+        } else {
+            best
+        }
+    };
+
+    let containing = candidates
+        .iter()
+        .copied()
+        .filter(|candidate| candidate.contains(point))
+        .reduce(innermost);
+    if containing.is_some() {
+        return containing;
+    }
+
+    let distance = candidates
+        .iter()
+        .map(|candidate| candidate.line_distance(point.line))
+        .min()?;
+    candidates
+        .iter()
+        .copied()
+        .filter(|candidate| candidate.line_distance(point.line) == distance)
+        .reduce(innermost)
+}
+
+/// Returns the `blocks` that share a position with `selection`, in their
+/// original order.
+pub fn overlapping(selection: SourcePos, blocks: &[SourcePos]) -> Vec<SourcePos> {
+    blocks
+        .iter()
+        .copied()
+        .filter(|block| block.overlaps(&selection))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(s: &str) -> SourcePos {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        let sourcepos = pos("3:1-5:12");
+        assert_eq!(sourcepos.start, LineColumn { line: 3, column: 1 });
+        assert_eq!(
+            sourcepos.end,
+            LineColumn {
+                line: 5,
+                column: 12
+            }
+        );
+        assert_eq!(sourcepos.to_string(), "3:1-5:12");
+
+        assert!("".parse::<SourcePos>().is_err());
+        assert!("3:1".parse::<SourcePos>().is_err());
+        assert!("3:1-x:2".parse::<SourcePos>().is_err());
+        assert!("\"3:1-3:2".parse::<SourcePos>().is_err());
+
+        assert_eq!(
+            "0:0-0:0".parse::<SourcePos>(),
+            Err(ParseSourcePosError::OutOfRange("0:0-0:0".to_string()))
+        );
+        assert!("5:1-4:9".parse::<SourcePos>().is_err());
+        assert!("5:3-5:2".parse::<SourcePos>().is_err());
+        assert!("5:0-6:2".parse::<SourcePos>().is_err());
+        assert_eq!(pos("5:1-7:0").end, LineColumn { line: 7, column: 0 });
+        assert_eq!(pos("1:1-1:1"), SourcePos::START);
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut positions = vec![
+            pos("3:1-3:5"),
+            pos("1:1-4:2"),
+            pos("1:1-1:7"),
+            pos("3:1-3:2"),
+        ];
+        positions.sort();
+        assert_eq!(
+            positions,
+            vec![
+                pos("1:1-1:7"),
+                pos("1:1-4:2"),
+                pos("3:1-3:2"),
+                pos("3:1-3:5")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_block_positions_in_html() {
+        let html = r#"<ul data-sourcepos="1:1-2:5">
+<li data-sourcepos="1:1-1:5">a <em data-sourcepos="1:5-1:7">b</em></li>
+<li data-sourcepos="bogus">b</li>
+</ul>"#;
+        assert_eq!(
+            block_positions_in_html(html).collect::<Vec<_>>(),
+            vec![pos("1:1-2:5"), pos("1:1-1:5")]
+        );
+    }
+
+    #[test]
+    fn test_resolve_prefers_innermost_containing_range() {
+        let candidates = [
+            pos("1:1-1:7"),
+            pos("3:1-6:10"),
+            pos("3:1-3:12"),
+            pos("4:1-6:10"),
+            pos("5:3-5:9"),
+        ];
+
+        assert_eq!(resolve(pos("5:4-5:4"), &candidates), Some(pos("5:3-5:9")));
+        assert_eq!(resolve(pos("3:7-3:7"), &candidates), Some(pos("3:1-3:12")));
+        assert_eq!(resolve(pos("6:2-6:2"), &candidates), Some(pos("4:1-6:10")));
+    }
+
+    #[test]
+    fn test_overlapping_blocks() {
+        let blocks = [
+            pos("1:1-1:7"),
+            pos("3:1-4:10"),
+            pos("6:1-6:5"),
+            pos("8:1-9:2"),
+        ];
+
+        assert_eq!(
+            overlapping(pos("4:3-6:2"), &blocks),
+            vec![pos("3:1-4:10"), pos("6:1-6:5")]
+        );
+        assert_eq!(overlapping(pos("1:7-1:7"), &blocks), vec![pos("1:1-1:7")]);
+        assert!(overlapping(pos("2:1-2:1"), &blocks).is_empty());
+    }
+
+    #[test]
+    fn test_resolve_falls_back_to_nearest_range() {
+        let candidates = [pos("1:1-1:7"), pos("3:1-4:10"), pos("9:1-9:5")];
+
+        // Past the end of a line, on a blank line and past the end of the file
+        assert_eq!(resolve(pos("1:20-1:20"), &candidates), Some(pos("1:1-1:7")));
+        assert_eq!(resolve(pos("2:1-2:1"), &candidates), Some(pos("1:1-1:7")));
+        assert_eq!(resolve(pos("7:1-7:1"), &candidates), Some(pos("9:1-9:5")));
+        assert_eq!(resolve(pos("42:7-42:7"), &candidates), Some(pos("9:1-9:5")));
+        assert_eq!(resolve(pos("1:1-1:1"), &[]), None);
+    }
+}
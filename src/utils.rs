@@ -1,6 +1,6 @@
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// Converts a path to an absolute path.
 ///
@@ -67,3 +67,151 @@ pub fn generate_document_id(filepath: &str) -> String {
 
     format!("{}-{}", filename, short_hash)
 }
+
+/// Returns true if the path has a markdown file extension (`.md` or `.markdown`).
+pub fn is_markdown_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"))
+}
+
+/// Recursively collects the markdown files under a directory.
+///
+/// Hidden files and directories (names starting with a dot) are skipped, and
+/// symbolic links to directories are not followed so that links pointing back
+/// up the tree cannot loop. The returned paths are sorted so that registration
+/// order is stable.
+pub fn find_markdown_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&current) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if hidden {
+                continue;
+            }
+
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                pending.push(path);
+            } else if is_markdown_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+/// Returns true if any component of `relative_path` is a hidden file or
+/// directory, such as `.env` or `.git`.
+pub fn is_hidden(relative_path: &Path) -> bool {
+    relative_path
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+/// Returns true if the file at `path`, once symlinks are resolved, lies inside
+/// `root` and is not hidden, so that it may be included into a document there.
+pub fn is_includable(root: &Path, path: &Path) -> bool {
+    path.canonicalize().is_ok_and(|resolved| {
+        resolved
+            .strip_prefix(root)
+            .is_ok_and(|relative_path| !is_hidden(relative_path))
+    })
+}
+
+/// Escapes the characters that are significant in HTML text and attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Decodes `%XX` escapes in a URL path, leaving malformed escapes as they are.
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = text.get(i + 1..i + 3)
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Builds a `/`-separated relative URL that leads from the directory `from_dir`
+/// to `to`. Both paths are expected to be absolute.
+pub fn relative_url(from_dir: &Path, to: &Path) -> String {
+    let from_components: Vec<_> = from_dir.components().collect();
+    let to_components: Vec<_> = to.components().collect();
+
+    let common = from_components
+        .iter()
+        .zip(&to_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut parts: Vec<String> = vec!["..".to_string(); from_components.len() - common];
+    parts.extend(
+        to_components[common..]
+            .iter()
+            .map(|component| component.as_os_str().to_string_lossy().to_string()),
+    );
+
+    parts.join("/")
+}
+
+/// Hashes file content to detect whether it changed between reads.
+pub fn hash_content(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Launches `browser_command` with `url` as its last argument. The command may
+/// carry its own arguments, e.g. `firefox --new-window`.
+///
+/// Returns once the process has started; it is reaped in the background.
+pub fn open_in_browser(browser_command: &str, url: &str) -> std::io::Result<()> {
+    let mut parts = browser_command.split_whitespace();
+    let program = parts.next().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "browser command is empty")
+    })?;
+
+    let mut child = std::process::Command::new(program)
+        .args(parts)
+        .arg(url)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
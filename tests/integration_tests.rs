@@ -1,13 +1,43 @@
 use axum::http::StatusCode;
 use axum_test::TestServer;
-use livemarkdown::{create_app, CreateDocumentRequest, CreateDocumentResponse};
+use livemarkdown::{
+    config, create_app, create_app_with_state, sourcepos::EditorPosition, utils, AppState,
+    CreateDocumentRequest, CreateDocumentResponse, DocumentInfo, DocumentListResponse,
+    ErrorResponse, UpdatePositionRequest,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Creates `relative_path` with some markdown content inside a fresh directory
+/// under TMPDIR and returns its absolute path.
+fn create_temp_file(relative_path: &str) -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let tmp_dir = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+    let dir = format!(
+        "{}/livemarkdown_test_{}_{}",
+        tmp_dir,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    );
+    let file_path = format!("{}/{}", dir, relative_path);
+    std::fs::create_dir_all(std::path::Path::new(&file_path).parent().unwrap()).unwrap();
+    std::fs::write(&file_path, "# Test document\n").unwrap();
+    file_path
+}
+
+fn create_document_body(filepath: &str) -> String {
+    facet_json::to_string(&CreateDocumentRequest {
+        filepath: filepath.to_string(),
+        extensions: None,
+    })
+}
 
 #[tokio::test]
 async fn test_create_document() {
     let app = create_app();
     let server = TestServer::new(app).unwrap();
 
-    let request_body = r#"{"filepath": "/path/to/test.md"}"#;
+    let request_body = create_document_body(&create_temp_file("test.md"));
 
     let response = server.post("/api/document").text(request_body).await;
 
@@ -29,11 +59,168 @@ async fn test_create_document_with_invalid_json() {
 
     let response = server.post("/api/document").text(request_body).await;
 
-    response.assert_status(StatusCode::CREATED);
-    let body_text = response.text();
-    assert!(body_text.contains("unknown"));
-    assert!(body_text.starts_with("{\"id\":\"unknown-"));
-    assert!(body_text.ends_with("\"}"));
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_header("content-type", "application/json");
+    let error: ErrorResponse = facet_json::from_str(&response.text()).unwrap();
+    assert!(error.error.starts_with("Invalid request body"));
+}
+
+#[tokio::test]
+async fn test_create_document_nonexistent_file() {
+    let app = create_app();
+    let server = TestServer::new(app).unwrap();
+
+    let request_body = r#"{"filepath": "/path/to/missing.md"}"#;
+
+    let response = server.post("/api/document").text(request_body).await;
+
+    response.assert_status(StatusCode::NOT_FOUND);
+    response.assert_header("content-type", "application/json");
+    response.assert_text(r#"{"error":"File not found: /path/to/missing.md"}"#);
+
+    // Nothing is registered for the missing file
+    let list_response = server.get("/api/documents").await;
+    list_response.assert_text(r#"{"documents":[]}"#);
+}
+
+#[tokio::test]
+async fn test_create_document_directory() {
+    let app = create_app();
+    let server = TestServer::new(app).unwrap();
+
+    let file_path = create_temp_file("notes/index.md");
+    let dir_path = std::path::Path::new(&file_path).parent().unwrap();
+    let request_body = create_document_body(dir_path.to_str().unwrap());
+
+    let response = server.post("/api/document").text(request_body).await;
+
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let error: ErrorResponse = facet_json::from_str(&response.text()).unwrap();
+    assert!(error.error.contains("is a directory"));
+}
+
+#[tokio::test]
+async fn test_create_document_with_extensions() {
+    let app = create_app();
+    let server = TestServer::new(app).unwrap();
+
+    let file_path = create_temp_file("dialect.md");
+    std::fs::write(&file_path, "| a |\n|---|\n| 1 |\n\n[[Wiki page]]\n").unwrap();
+
+    let request_body = format!(
+        r#"{{"filepath":"{}","extensions":{{"preset":"commonmark","enable":["wikilinks"]}}}}"#,
+        file_path
+    );
+    let create_response = server.post("/api/document").text(&request_body).await;
+    create_response.assert_status(StatusCode::CREATED);
+    let doc_id = facet_json::from_str::<CreateDocumentResponse>(&create_response.text())
+        .unwrap()
+        .id;
+
+    let content = server.get(&format!("/document/{}", doc_id)).await.text();
+    assert!(!content.contains("<table"));
+    assert!(content.contains(r#"href="Wiki%20page""#));
+
+    // Registering the file again switches its extensions
+    let request_body = format!(
+        r#"{{"filepath":"{}","extensions":{{"preset":"gfm"}}}}"#,
+        file_path
+    );
+    server
+        .post("/api/document")
+        .text(&request_body)
+        .await
+        .assert_status(StatusCode::CREATED);
+    let content = server.get(&format!("/document/{}", doc_id)).await.text();
+    assert!(content.contains("<table"));
+    assert!(content.contains("[[Wiki page]]"));
+}
+
+#[tokio::test]
+async fn test_front_matter_sets_title_and_metadata() {
+    let state = AppState::new();
+    let server = TestServer::new(create_app_with_state(state.clone())).unwrap();
+
+    let file_path = create_temp_file("notes.md");
+    std::fs::write(
+        &file_path,
+        "---\ntitle: Design <notes>\nauthor: Ada\ndate: 2024-05-01\ntags: [design, rfc]\n---\n\n# Body\n",
+    )
+    .unwrap();
+    let doc_id = facet_json::from_str::<CreateDocumentResponse>(
+        &server
+            .post("/api/document")
+            .text(create_document_body(&file_path))
+            .await
+            .text(),
+    )
+    .unwrap()
+    .id;
+
+    let content = server.get(&format!("/document/{}", doc_id)).await.text();
+    assert!(content.contains("<title>Design &lt;notes&gt;</title>"));
+    assert!(content.contains(r#"<header class="document-metadata""#));
+    assert!(content.contains(r#"<time class="document-date" datetime="2024-05-01">"#));
+    assert!(!content.contains("author: Ada"));
+
+    let response = server.get(&format!("/api/document/{}", doc_id)).await;
+    let info: DocumentInfo = facet_json::from_str(&response.text()).unwrap();
+    assert_eq!(info.title, "Design <notes>");
+    assert_eq!(info.author.as_deref(), Some("Ada"));
+    assert_eq!(info.date.as_deref(), Some("2024-05-01"));
+    assert_eq!(info.tags, vec!["design", "rfc"]);
+
+    // Without the extension the front matter is neither parsed nor hidden
+    state.set_document_extensions(&doc_id, config::ExtensionSet::gfm());
+    let response = server.get(&format!("/api/document/{}", doc_id)).await;
+    let info: DocumentInfo = facet_json::from_str(&response.text()).unwrap();
+    assert_eq!(info.title, "notes");
+    assert_eq!(info.author, None);
+    assert!(info.tags.is_empty());
+}
+
+#[tokio::test]
+async fn test_document_info_does_not_render_documents() {
+    let state = AppState::new();
+    let server = TestServer::new(create_app_with_state(state.clone())).unwrap();
+
+    let file_path = create_temp_file("index.md");
+    let other_path = std::path::Path::new(&file_path).with_file_name("other.md");
+    std::fs::write(&other_path, "# Other\n").unwrap();
+    std::fs::write(&file_path, "---\ntitle: Index\n---\n\n[Other](other.md)\n").unwrap();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path.clone());
+
+    let response = server.get("/api/documents").await;
+    let list: DocumentListResponse = facet_json::from_str(&response.text()).unwrap();
+    assert_eq!(list.documents.len(), 1);
+    assert_eq!(list.documents[0].title, "Index");
+    // Rendering would have registered the linked document
+    assert_eq!(state.get_all_documents().len(), 1);
+    assert!(state.get_front_matter(&doc_id).is_none());
+
+    // Unsaved content is read instead of the file
+    state.set_unsaved_content(&doc_id, "---\ntitle: Draft\n---\n".to_string());
+    let response = server.get(&format!("/api/document/{}", doc_id)).await;
+    let info: DocumentInfo = facet_json::from_str(&response.text()).unwrap();
+    assert_eq!(info.title, "Draft");
+    assert_eq!(state.get_all_documents().len(), 1);
+}
+
+#[tokio::test]
+async fn test_create_document_with_unknown_extension() {
+    let app = create_app();
+    let server = TestServer::new(app).unwrap();
+
+    let request_body = format!(
+        r#"{{"filepath":"{}","extensions":{{"enable":["tables"]}}}}"#,
+        create_temp_file("dialect.md")
+    );
+    let response = server.post("/api/document").text(&request_body).await;
+
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let error: ErrorResponse = facet_json::from_str(&response.text()).unwrap();
+    assert!(error.error.starts_with("unknown extension \"tables\""));
 }
 
 #[tokio::test]
@@ -42,7 +229,7 @@ async fn test_delete_document() {
     let server = TestServer::new(app).unwrap();
 
     // Create a document first
-    let request_body = r#"{"filepath": "/path/to/delete-test.md"}"#;
+    let request_body = create_document_body(&create_temp_file("delete-test.md"));
     let create_response = server.post("/api/document").text(request_body).await;
 
     create_response.assert_status(StatusCode::CREATED);
@@ -66,18 +253,94 @@ async fn test_delete_nonexistent_document() {
 
     let response = server.delete("/api/document/nonexistent-id").await;
     response.assert_status(StatusCode::NOT_FOUND);
+    response.assert_text(r#"{"error":"Document not found"}"#);
 }
 
 #[tokio::test]
 async fn test_open_document() {
-    let app = create_app();
-    let server = TestServer::new(app).unwrap();
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::time::{sleep, Duration};
 
-    let response = server.post("/api/document/test-doc-id/open").await;
+    // A fake browser that records the URL it was asked to open
+    let file_path = create_temp_file("open.md");
+    let dir = std::path::Path::new(&file_path).parent().unwrap();
+    let opened_path = dir.join("opened.txt");
+    let browser_path = dir.join("browser.sh");
+    std::fs::write(
+        &browser_path,
+        format!("#!/bin/sh\necho \"$1\" > {}\n", opened_path.display()),
+    )
+    .unwrap();
+    std::fs::set_permissions(&browser_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let state = AppState::new()
+        .with_browser_command(browser_path.to_str().unwrap())
+        .with_server_addr("127.0.0.1:4242".parse().unwrap());
+    let server = TestServer::new(create_app_with_state(state)).unwrap();
+
+    let create_response = server
+        .post("/api/document")
+        .text(create_document_body(&file_path))
+        .await;
+    let doc_id = facet_json::from_str::<CreateDocumentResponse>(&create_response.text())
+        .unwrap()
+        .id;
+
+    let response = server
+        .post(&format!("/api/document/{}/open", doc_id))
+        .add_header("host", "attacker.example:4242")
+        .await;
 
     response.assert_status(StatusCode::CREATED);
     response.assert_header("content-type", "text/plain");
     response.assert_text("Document opened");
+
+    let mut opened = String::new();
+    for _ in 0..50 {
+        opened = std::fs::read_to_string(&opened_path).unwrap_or_default();
+        if !opened.is_empty() {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(
+        opened.trim(),
+        format!("http://127.0.0.1:4242/document/{}", doc_id)
+    );
+}
+
+#[tokio::test]
+async fn test_open_nonexistent_document() {
+    let app = create_app();
+    let server = TestServer::new(app).unwrap();
+
+    let response = server.post("/api/document/test-doc-id/open").await;
+
+    response.assert_status(StatusCode::NOT_FOUND);
+    response.assert_text(r#"{"error":"Document not found"}"#);
+}
+
+#[tokio::test]
+async fn test_open_document_browser_failure() {
+    let state = AppState::new().with_browser_command("/nonexistent/browser");
+    let server = TestServer::new(create_app_with_state(state)).unwrap();
+
+    let create_response = server
+        .post("/api/document")
+        .text(create_document_body(&create_temp_file("open.md")))
+        .await;
+    let doc_id = facet_json::from_str::<CreateDocumentResponse>(&create_response.text())
+        .unwrap()
+        .id;
+
+    let response = server
+        .post(&format!("/api/document/{}/open", doc_id))
+        .add_header("host", "localhost:4242")
+        .await;
+
+    response.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    let error: ErrorResponse = facet_json::from_str(&response.text()).unwrap();
+    assert!(error.error.starts_with("Failed to launch browser"));
 }
 
 #[tokio::test]
@@ -85,14 +348,43 @@ async fn test_update_position() {
     let app = create_app();
     let server = TestServer::new(app).unwrap();
 
+    let create_response = server
+        .post("/api/document")
+        .text(create_document_body(&create_temp_file("position.md")))
+        .await;
+    let doc_id = facet_json::from_str::<CreateDocumentResponse>(&create_response.text())
+        .unwrap()
+        .id;
+
     let request_body = r#"{"sourcepos": "1:1-2:5"}"#;
 
     let response = server
-        .post("/api/document/test-doc-id/position")
+        .post(&format!("/api/document/{}/position", doc_id))
         .text(request_body)
         .await;
 
     response.assert_status(StatusCode::CREATED);
+
+    let info_response = server.get(&format!("/api/document/{}", doc_id)).await;
+    let info: DocumentInfo = facet_json::from_str(&info_response.text()).unwrap();
+    assert_eq!(info.sourcepos, "1:1-2:5");
+}
+
+#[tokio::test]
+async fn test_update_position_unknown_document() {
+    let app = create_app();
+    let server = TestServer::new(app).unwrap();
+
+    let request_body = r#"{"sourcepos": "1:1-2:5"}"#;
+
+    let response = server
+        .post("/api/document/test-doc-id/position")
+        .text(request_body)
+        .await;
+
+    response.assert_status(StatusCode::NOT_FOUND);
+    response.assert_header("content-type", "application/json");
+    response.assert_text(r#"{"error":"Document not found"}"#);
 }
 
 #[tokio::test]
@@ -100,14 +392,71 @@ async fn test_update_position_with_invalid_json() {
     let app = create_app();
     let server = TestServer::new(app).unwrap();
 
+    let create_response = server
+        .post("/api/document")
+        .text(create_document_body(&create_temp_file("position.md")))
+        .await;
+    let doc_id = facet_json::from_str::<CreateDocumentResponse>(&create_response.text())
+        .unwrap()
+        .id;
+
     let request_body = r#"{"invalid": "json"}"#;
 
     let response = server
-        .post("/api/document/test-doc-id/position")
+        .post(&format!("/api/document/{}/position", doc_id))
         .text(request_body)
         .await;
 
-    response.assert_status(StatusCode::CREATED);
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let error: ErrorResponse = facet_json::from_str(&response.text()).unwrap();
+    assert!(error.error.starts_with("Invalid request body"));
+
+    // The stored position is left untouched
+    let info_response = server.get(&format!("/api/document/{}", doc_id)).await;
+    let info: DocumentInfo = facet_json::from_str(&info_response.text()).unwrap();
+    assert_eq!(info.sourcepos, "1:1-1:1");
+}
+
+#[tokio::test]
+async fn test_update_position_with_invalid_sourcepos() {
+    let state = AppState::new();
+    let server = TestServer::new(create_app_with_state(state.clone())).unwrap();
+
+    let create_response = server
+        .post("/api/document")
+        .text(create_document_body(&create_temp_file("position.md")))
+        .await;
+    let doc_id = facet_json::from_str::<CreateDocumentResponse>(&create_response.text())
+        .unwrap()
+        .id;
+
+    for sourcepos in ["", "12", "1:1-1:x", r#"1:1-1:1\"}"#, "0:1-0:1", "4:1-2:1"] {
+        let response = server
+            .post(&format!("/api/document/{}/position", doc_id))
+            .text(facet_json::to_string(&UpdatePositionRequest {
+                sourcepos: sourcepos.to_string(),
+                selection: None,
+            }))
+            .await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let error: ErrorResponse = facet_json::from_str(&response.text()).unwrap();
+        assert!(
+            error.error.starts_with("invalid sourcepos"),
+            "{}",
+            error.error
+        );
+
+        server
+            .post(&format!("/api/document/{}/scroll", doc_id))
+            .text(facet_json::to_string(&UpdatePositionRequest {
+                sourcepos: sourcepos.to_string(),
+                selection: None,
+            }))
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    assert_eq!(state.get_position(&doc_id), Some(EditorPosition::START));
 }
 
 #[tokio::test]
@@ -124,6 +473,7 @@ async fn test_serve_document() {
     let example_path = std::env::current_dir().unwrap().join("examples/simple.md");
     let create_request = CreateDocumentRequest {
         filepath: example_path.to_str().unwrap().to_string(),
+        extensions: None,
     };
     let create_body = facet_json::to_string(&create_request);
 
@@ -147,10 +497,10 @@ async fn test_create_document_duplicate_filepath() {
     let app = create_app();
     let server = TestServer::new(app).unwrap();
 
-    let request_body = r#"{"filepath": "/path/to/duplicate.md"}"#;
+    let request_body = create_document_body(&create_temp_file("duplicate.md"));
 
     // Create first document
-    let response1 = server.post("/api/document").text(request_body).await;
+    let response1 = server.post("/api/document").text(&request_body).await;
 
     response1.assert_status(StatusCode::CREATED);
     let body1 = response1.text();
@@ -175,10 +525,8 @@ async fn test_create_document_empty_filepath() {
 
     let response = server.post("/api/document").text(request_body).await;
 
-    response.assert_status(StatusCode::CREATED);
-    let body_text = response.text();
-    assert!(body_text.contains("unknown"));
-    assert!(body_text.starts_with("{\"id\":\"unknown-"));
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text(r#"{"error":"filepath must not be empty"}"#);
 }
 
 #[tokio::test]
@@ -186,7 +534,7 @@ async fn test_create_document_nested_path() {
     let app = create_app();
     let server = TestServer::new(app).unwrap();
 
-    let request_body = r#"{"filepath": "/deep/nested/path/to/readme.md"}"#;
+    let request_body = create_document_body(&create_temp_file("deep/nested/path/to/readme.md"));
 
     let response = server.post("/api/document").text(request_body).await;
 
@@ -201,7 +549,7 @@ async fn test_create_document_special_characters_in_filename() {
     let app = create_app();
     let server = TestServer::new(app).unwrap();
 
-    let request_body = r#"{"filepath": "/path/my-file.name.with.dots.md"}"#;
+    let request_body = create_document_body(&create_temp_file("my-file.name.with.dots.md"));
 
     let response = server.post("/api/document").text(request_body).await;
 
@@ -221,11 +569,9 @@ async fn test_create_document_malformed_json() {
 
     let response = server.post("/api/document").text(request_body).await;
 
-    response.assert_status(StatusCode::CREATED);
-    let body_text = response.text();
-    // Should fallback to "unknown" when JSON parsing fails
-    assert!(body_text.contains("unknown"));
-    assert!(body_text.starts_with("{\"id\":\"unknown-"));
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let error: ErrorResponse = facet_json::from_str(&response.text()).unwrap();
+    assert!(error.error.starts_with("Invalid request body"));
 }
 
 #[tokio::test]
@@ -233,7 +579,7 @@ async fn test_create_document_id_format_consistency() {
     let app = create_app();
     let server = TestServer::new(app).unwrap();
 
-    let request_body = r#"{"filepath": "/test/consistency.md"}"#;
+    let request_body = create_document_body(&create_temp_file("consistency.md"));
 
     let response = server.post("/api/document").text(request_body).await;
 
@@ -257,7 +603,7 @@ async fn test_create_document_no_extension() {
     let app = create_app();
     let server = TestServer::new(app).unwrap();
 
-    let request_body = r#"{"filepath": "/path/to/README"}"#;
+    let request_body = create_document_body(&create_temp_file("README"));
 
     let response = server.post("/api/document").text(request_body).await;
 
@@ -292,13 +638,15 @@ async fn test_list_documents() {
     assert!(body_text.contains("<ul>"));
 
     // Create some documents
-    let request1 = r#"{"filepath": "/path/to/first.md"}"#;
+    let first_path = create_temp_file("first.md");
+    let request1 = create_document_body(&first_path);
     let create_response1 = server.post("/api/document").text(request1).await;
     create_response1.assert_status(StatusCode::CREATED);
     let create_body1: CreateDocumentResponse =
         facet_json::from_str(&create_response1.text()).unwrap();
 
-    let request2 = r#"{"filepath": "/path/to/second.md"}"#;
+    let second_path = create_temp_file("second.md");
+    let request2 = create_document_body(&second_path);
     let create_response2 = server.post("/api/document").text(request2).await;
     create_response2.assert_status(StatusCode::CREATED);
     let create_body2: CreateDocumentResponse =
@@ -310,9 +658,9 @@ async fn test_list_documents() {
     let list_body = list_response.text();
 
     assert!(list_body.contains(&format!("/document/{}", create_body1.id)));
-    assert!(list_body.contains("/path/to/first.md"));
+    assert!(list_body.contains(&first_path));
     assert!(list_body.contains(&format!("/document/{}", create_body2.id)));
-    assert!(list_body.contains("/path/to/second.md"));
+    assert!(list_body.contains(&second_path));
 }
 
 #[tokio::test]
@@ -321,7 +669,7 @@ async fn test_sse_endpoint_exists() {
     let server = TestServer::new(app).unwrap();
 
     // First create a document
-    let request_body = r#"{"filepath": "/path/to/test.md"}"#;
+    let request_body = create_document_body(&create_temp_file("test.md"));
     let create_response = server.post("/api/document").text(request_body).await;
     create_response.assert_status(StatusCode::CREATED);
 
@@ -390,9 +738,8 @@ async fn test_file_watcher_integration() {
     let sse_response = tokio::time::timeout(std::time::Duration::from_secs(1), sse_request).await;
 
     // SSE should connect successfully (timeout is expected)
-    match sse_response {
-        Ok(resp) => resp.assert_status_ok(),
-        Err(_) => {} // Timeout is expected for SSE
+    if let Ok(resp) = sse_response {
+        resp.assert_status_ok();
     }
 
     // Clean up
@@ -446,12 +793,1016 @@ async fn test_file_change_notification_via_sse() {
     let sse_request = server.get(&format!("/document/{}/updates", doc_id));
     let sse_response = tokio::time::timeout(std::time::Duration::from_secs(1), sse_request).await;
 
-    // SSE should still connect successfully
-    match sse_response {
-        Ok(resp) => resp.assert_status_ok(),
-        Err(_) => {} // Timeout is expected for SSE
+    // SSE should still connect successfully (timeout is expected)
+    if let Ok(resp) = sse_response {
+        resp.assert_status_ok();
     }
 
     // Clean up
     let _ = fs::remove_file(&file_path);
 }
+
+#[tokio::test]
+async fn test_directory_mode() {
+    use std::env;
+    use std::fs;
+    use tokio::time::{sleep, Duration};
+
+    // Create a temporary directory tree using TMPDIR
+    let tmp_dir = env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+    let dir_path = format!("{}/test_directory_mode_{}", tmp_dir, std::process::id());
+    let _ = fs::remove_dir_all(&dir_path);
+    fs::create_dir_all(format!("{}/guide", dir_path)).unwrap();
+    fs::write(format!("{}/index.md", dir_path), "# Index").unwrap();
+    fs::write(format!("{}/guide/setup.md", dir_path), "# Setup").unwrap();
+    fs::write(format!("{}/notes.txt", dir_path), "not markdown").unwrap();
+
+    let state = AppState::new();
+    let doc_ids = state.add_directory(&dir_path);
+    assert_eq!(doc_ids.len(), 2);
+
+    let server = TestServer::new(create_app_with_state(state)).unwrap();
+
+    // The index shows the documents as a tree
+    let list_body = server.get("/").await.text();
+    assert!(list_body.contains("<summary>guide/</summary>"));
+    assert!(list_body.contains(">index.md</a>"));
+    assert!(list_body.contains(">setup.md</a>"));
+    assert!(!list_body.contains("notes.txt"));
+
+    let serve_response = server.get(&format!("/document/{}", doc_ids[0])).await;
+    serve_response.assert_status_ok();
+
+    // Files created later are picked up
+    sleep(Duration::from_millis(100)).await;
+    fs::write(format!("{}/guide/later.md", dir_path), "# Later").unwrap();
+    sleep(Duration::from_millis(1000)).await;
+
+    let list_body = server.get("/").await.text();
+    assert!(list_body.contains(">later.md</a>"));
+
+    // Deleted files are dropped
+    fs::remove_file(format!("{}/guide/setup.md", dir_path)).unwrap();
+    sleep(Duration::from_millis(1000)).await;
+
+    let list_body = server.get("/").await.text();
+    assert!(!list_body.contains(">setup.md</a>"));
+    assert!(list_body.contains(">later.md</a>"));
+
+    // Clean up
+    let _ = fs::remove_dir_all(&dir_path);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_directory_mode_does_not_follow_symlink_loops() {
+    let file_path = create_temp_file("docs/index.md");
+    let dir = std::path::Path::new(&file_path).parent().unwrap();
+    std::os::unix::fs::symlink(dir, dir.join("a")).unwrap();
+    std::os::unix::fs::symlink(dir, dir.join("b")).unwrap();
+
+    let state = AppState::new();
+    let doc_ids = state.add_directory(&dir.to_string_lossy());
+    assert_eq!(doc_ids, vec![utils::generate_document_id(&file_path)]);
+}
+
+#[tokio::test]
+async fn test_relative_links_between_documents() {
+    use std::env;
+    use std::fs;
+
+    // Create two cross-linked files using TMPDIR
+    let tmp_dir = env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+    let dir_path = format!("{}/test_relative_links_{}", tmp_dir, std::process::id());
+    let _ = fs::remove_dir_all(&dir_path);
+    fs::create_dir_all(format!("{}/design", dir_path)).unwrap();
+    fs::write(
+        format!("{}/index.md", dir_path),
+        "# Index\n\nSee [the design](./design/overview.md#goals).",
+    )
+    .unwrap();
+    fs::write(
+        format!("{}/design/overview.md", dir_path),
+        "# Overview\n\nBack to [index](../index.md).",
+    )
+    .unwrap();
+
+    let app = create_app();
+    let server = TestServer::new(app).unwrap();
+
+    let request_body = format!(r#"{{"filepath":"{}/index.md"}}"#, dir_path);
+    let create_response = server.post("/api/document").text(&request_body).await;
+    create_response.assert_status(StatusCode::CREATED);
+    let index_id = facet_json::from_str::<CreateDocumentResponse>(&create_response.text())
+        .unwrap()
+        .id;
+
+    // The linked document is registered on demand and the link points at it
+    let serve_response = server.get(&format!("/document/{}", index_id)).await;
+    serve_response.assert_status_ok();
+    let content = serve_response.text();
+    assert!(!content.contains("./design/overview.md"));
+    assert!(content.contains(r#"href="/document/overview-md-"#));
+    assert!(content.contains(r##"#goals""##));
+
+    let list_body = server.get("/").await.text();
+    assert!(list_body.contains(">overview.md</a>"));
+
+    // The link back resolves to the already registered document
+    let overview_href_start = content.find("/document/overview-md-").unwrap();
+    let overview_href_end = content[overview_href_start..].find('#').unwrap();
+    let overview_url = &content[overview_href_start..overview_href_start + overview_href_end];
+    let overview_content = server.get(overview_url).await.text();
+    assert!(overview_content.contains(&format!(r#"href="/document/{}""#, index_id)));
+
+    // Clean up
+    let _ = fs::remove_dir_all(&dir_path);
+}
+
+#[tokio::test]
+async fn test_serve_document_assets() {
+    use std::env;
+    use std::fs;
+
+    // Create a document with a local image using TMPDIR
+    let tmp_dir = env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+    let dir_path = format!("{}/test_document_assets_{}", tmp_dir, std::process::id());
+    let _ = fs::remove_dir_all(&dir_path);
+    fs::create_dir_all(format!("{}/docs/img", dir_path)).unwrap();
+    fs::write(format!("{}/docs/img/arch.png", dir_path), b"\x89PNG\r\n").unwrap();
+    fs::write(format!("{}/secret.txt", dir_path), "top secret").unwrap();
+    fs::write(format!("{}/docs/.env", dir_path), "TOKEN=secret").unwrap();
+    fs::create_dir_all(format!("{}/docs/.git", dir_path)).unwrap();
+    fs::write(format!("{}/docs/.git/config", dir_path), "[core]").unwrap();
+    fs::write(
+        format!("{}/docs/design.md", dir_path),
+        "# Design\n\n![diagram](./img/arch.png)",
+    )
+    .unwrap();
+
+    let app = create_app();
+    let server = TestServer::new(app).unwrap();
+
+    let request_body = format!(r#"{{"filepath":"{}/docs/design.md"}}"#, dir_path);
+    let create_response = server.post("/api/document").text(&request_body).await;
+    create_response.assert_status(StatusCode::CREATED);
+    let doc_id = facet_json::from_str::<CreateDocumentResponse>(&create_response.text())
+        .unwrap()
+        .id;
+
+    // The image is rewritten to the document's asset route
+    let content = server.get(&format!("/document/{}", doc_id)).await.text();
+    assert!(content.contains(&format!(
+        r#"src="/document/{}/assets/img/arch.png?v="#,
+        doc_id
+    )));
+
+    let asset_response = server
+        .get(&format!("/document/{}/assets/img/arch.png", doc_id))
+        .await;
+    asset_response.assert_status_ok();
+    asset_response.assert_header("content-type", "image/png");
+
+    // Missing assets and unknown documents are not found
+    server
+        .get(&format!("/document/{}/assets/img/missing.png", doc_id))
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server
+        .get("/document/nonexistent-id/assets/img/arch.png")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+
+    // Hidden files next to the document are refused
+    for hidden_path in [".env", ".git/config", "img/../.env"] {
+        server
+            .get(&format!("/document/{}/assets/{}", doc_id, hidden_path))
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+    }
+
+    // Files outside the document's root are refused
+    server
+        .get(&format!("/document/{}/assets/..%2Fsecret.txt", doc_id))
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+
+    // Clean up
+    let _ = fs::remove_dir_all(&dir_path);
+}
+
+#[tokio::test]
+async fn test_mermaid_diagrams_use_bundled_script() {
+    let server = TestServer::new(create_app()).unwrap();
+
+    let file_path = create_temp_file("diagram.md");
+    std::fs::write(
+        &file_path,
+        "# Flow\n\n```mermaid\ngraph TD\n  A --> B\n```\n",
+    )
+    .unwrap();
+    let create_response = server
+        .post("/api/document")
+        .text(create_document_body(&file_path))
+        .await;
+    let doc_id = facet_json::from_str::<CreateDocumentResponse>(&create_response.text())
+        .unwrap()
+        .id;
+
+    // The diagram source is left for the page script to render
+    let content = server.get(&format!("/document/{}", doc_id)).await.text();
+    assert!(content.contains(
+        "<pre data-sourcepos=\"3:1-6:3\"><code class=\"language-mermaid\">graph TD\n  A --&gt; B\n</code></pre>"
+    ));
+    assert!(content.contains("'mermaid.min.js'"));
+
+    let response = server.get("/assets/mermaid.min.js").await;
+    response.assert_status_ok();
+    response.assert_header("content-type", "application/javascript");
+    // The pinned release itself, defining the global the page script uses
+    let script = response.text();
+    assert!(script.contains("exports.mermaid=e():t.mermaid=e()"));
+    assert!(script.contains("initialize:function"));
+    assert!(script.contains("\"9.1.5\""));
+
+    server
+        .get("/assets/unknown.js")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_math_uses_bundled_katex() {
+    let server = TestServer::new(create_app()).unwrap();
+
+    let file_path = create_temp_file("math.md");
+    std::fs::write(&file_path, "Euler: $e^{i\\pi} + 1 = 0$\n").unwrap();
+    let create_response = server
+        .post("/api/document")
+        .text(create_document_body(&file_path))
+        .await;
+    let doc_id = facet_json::from_str::<CreateDocumentResponse>(&create_response.text())
+        .unwrap()
+        .id;
+
+    let content = server.get(&format!("/document/{}", doc_id)).await.text();
+    assert!(content.contains(r#"data-math-style="inline""#));
+    assert!(content.contains("'katex.min.js'"));
+
+    let response = server.get("/assets/katex.min.js").await;
+    response.assert_status_ok();
+    response.assert_header("content-type", "application/javascript");
+    assert!(response.text().contains("katex"));
+}
+
+/// Minimal SSE client reading raw HTTP from a real listener, since SSE
+/// responses never complete under `TestServer`.
+struct SseClient {
+    stream: tokio::net::TcpStream,
+    buffer: String,
+    /// The types of the events read so far, including skipped ones.
+    received: Vec<String>,
+    last_event_id: Option<String>,
+}
+
+impl SseClient {
+    async fn connect(app: axum::Router, path: &str) -> Self {
+        Self::connect_with_headers(app, path, "").await
+    }
+
+    /// Connects with extra `headers`, each followed by `\r\n`.
+    async fn connect_with_headers(app: axum::Router, path: &str, headers: &str) -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nAccept: text/event-stream\r\n{}\r\n",
+            path, addr, headers
+        );
+        tokio::io::AsyncWriteExt::write_all(&mut stream, request.as_bytes())
+            .await
+            .unwrap();
+
+        SseClient {
+            stream,
+            buffer: String::new(),
+            received: Vec::new(),
+            last_event_id: None,
+        }
+    }
+
+    /// Waits for the response headers, after which the server is subscribed.
+    async fn wait_until_connected(&mut self) {
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while !self.buffer.contains("\r\n\r\n") {
+                self.read_chunk().await;
+            }
+        })
+        .await
+        .expect("timed out waiting for the response")
+    }
+
+    /// Reads until an event of the given type arrives and returns its data.
+    async fn next_event(&mut self, event_type: &str) -> String {
+        let marker = format!("event: {}\n", event_type);
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                if let Some(start) = self.buffer.find(&marker) {
+                    let rest = &self.buffer[start + marker.len()..];
+                    if let Some(end) = rest.find("\n\n") {
+                        let data = rest[..end]
+                            .lines()
+                            .filter_map(|line| line.strip_prefix("data: "))
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        self.last_event_id = rest[..end]
+                            .lines()
+                            .find_map(|line| line.strip_prefix("id: "))
+                            .map(str::to_string);
+                        self.received.extend(
+                            self.buffer[..start + marker.len()]
+                                .lines()
+                                .filter_map(|line| line.strip_prefix("event: "))
+                                .map(str::to_string),
+                        );
+                        self.buffer = rest[end..].to_string();
+                        return data;
+                    }
+                }
+
+                self.read_chunk().await;
+            }
+        })
+        .await
+        .expect("timed out waiting for event")
+    }
+
+    async fn read_chunk(&mut self) {
+        let mut chunk = [0u8; 4096];
+        let read = tokio::io::AsyncReadExt::read(&mut self.stream, &mut chunk)
+            .await
+            .unwrap();
+        assert!(read > 0, "connection closed");
+        self.buffer
+            .push_str(&String::from_utf8_lossy(&chunk[..read]));
+    }
+}
+
+#[tokio::test]
+async fn test_file_change_sends_block_patch() {
+    use std::env;
+    use std::fs;
+    use tokio::time::{sleep, Duration};
+
+    // Create a temporary file using TMPDIR
+    let tmp_dir = env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+    let file_path = format!("{}/test_block_patch_{}.md", tmp_dir, std::process::id());
+    fs::write(
+        &file_path,
+        "# Title\n\nFirst paragraph\n\nSecond paragraph\n",
+    )
+    .unwrap();
+
+    let state = AppState::new();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path.clone());
+
+    let mut client = SseClient::connect(
+        create_app_with_state(state),
+        &format!("/document/{}/updates", doc_id),
+    )
+    .await;
+    client.next_event("position").await;
+
+    // Give the file watcher a moment to initialize
+    sleep(Duration::from_millis(100)).await;
+    fs::write(
+        &file_path,
+        "# Title\n\nFirst paragraph\n\nChanged paragraph\n",
+    )
+    .unwrap();
+
+    // Only the changed block carries HTML
+    let data = client.next_event("file_changed").await;
+    assert!(data.contains(r#"{"sourcepos":"1:1-1:7","html":null}"#));
+    assert!(data.contains(r#"{"sourcepos":"3:1-3:15","html":null}"#));
+    assert!(data.contains(r#"{"sourcepos":"5:1-5:17","html":"<p data-sourcepos=\"5:1-5:17\">Changed paragraph</p>\n"}"#));
+    assert!(!data.contains("Second paragraph"));
+
+    // Clean up
+    let _ = fs::remove_file(&file_path);
+}
+
+#[tokio::test]
+async fn test_file_change_updates_outline() {
+    use tokio::time::{sleep, Duration};
+
+    let file_path = create_temp_file("outline.md");
+    std::fs::write(&file_path, "# Title\n\nText\n").unwrap();
+
+    let state = AppState::new();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path.clone());
+
+    let server = TestServer::new(create_app_with_state(state.clone())).unwrap();
+    let content = server.get(&format!("/document/{}", doc_id)).await.text();
+    assert!(content.contains(r##"<a href="#title" data-sourcepos="1:1-1:7">Title</a>"##));
+
+    let mut client = SseClient::connect(
+        create_app_with_state(state),
+        &format!("/document/{}/updates", doc_id),
+    )
+    .await;
+    client.next_event("position").await;
+
+    sleep(Duration::from_millis(100)).await;
+    std::fs::write(&file_path, "# Title\n\nText\n\n## Usage\n").unwrap();
+
+    let data = client.next_event("file_changed").await;
+    assert!(data.contains(
+        r#""outline":[{"level":1,"text":"Title","anchor":"title","sourcepos":"1:1-1:7"},{"level":2,"text":"Usage","anchor":"usage","sourcepos":"5:1-5:8"}]"#
+    ));
+}
+
+#[tokio::test]
+async fn test_outline_links_to_each_duplicate_heading() {
+    let file_path = create_temp_file("examples.md");
+    std::fs::write(&file_path, "## Example\n\nOne\n\n## Example\n\nTwo\n").unwrap();
+
+    let state = AppState::new();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path);
+
+    let server = TestServer::new(create_app_with_state(state)).unwrap();
+    let content = server.get(&format!("/document/{}", doc_id)).await.text();
+    for (anchor, sourcepos) in [("example", "1:1-1:10"), ("example-1", "5:1-5:10")] {
+        assert!(content.contains(&format!(
+            r##"<a href="#{}" data-sourcepos="{}">Example</a>"##,
+            anchor, sourcepos
+        )));
+        assert_eq!(content.matches(&format!("id=\"{}\"", anchor)).count(), 1);
+    }
+}
+
+#[tokio::test]
+async fn test_includes_outside_document_root_are_refused() {
+    let file_path = create_temp_file("docs/guide.md");
+    let docs_dir = std::path::Path::new(&file_path).parent().unwrap();
+    std::fs::write(docs_dir.join("snippet.md"), "Shared text\n").unwrap();
+    std::fs::write(docs_dir.join(".env"), "TOKEN=hidden-token\n").unwrap();
+    std::fs::write(docs_dir.join("../secret.txt"), "outside-root\n").unwrap();
+    std::fs::write(
+        &file_path,
+        "{{#include snippet.md}}\n\n{{#include .env}}\n\n{{#include ../secret.txt}}\n",
+    )
+    .unwrap();
+
+    let state = AppState::new();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path.clone());
+
+    let server = TestServer::new(create_app_with_state(state)).unwrap();
+    let content = server.get(&format!("/document/{}", doc_id)).await.text();
+    assert!(content.contains("<p>Shared text</p>"));
+    assert!(!content.contains("hidden-token"));
+    assert!(!content.contains("outside-root"));
+    assert!(content.contains("{{#include ../secret.txt}}"));
+}
+
+#[tokio::test]
+async fn test_included_file_changes_refresh_including_document() {
+    use tokio::time::{sleep, Duration};
+
+    let file_path = create_temp_file("guide.md");
+    std::fs::write(&file_path, "# Guide\n\n{{#include ./snippets/api.md}}\n").unwrap();
+    let dir = std::path::Path::new(&file_path).parent().unwrap();
+    let snippet_path = dir.join("snippets/api.md").to_string_lossy().to_string();
+    std::fs::create_dir_all(dir.join("snippets")).unwrap();
+    std::fs::write(&snippet_path, "Call `list()`\n").unwrap();
+
+    let state = AppState::new();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path.clone());
+
+    let server = TestServer::new(create_app_with_state(state.clone())).unwrap();
+    let content = server.get(&format!("/document/{}", doc_id)).await.text();
+    assert!(content.contains(
+        "<div class=\"include\" data-sourcepos=\"3:1-3:30\">\n<p>Call <code>list()</code></p>"
+    ));
+
+    let mut client = SseClient::connect(
+        create_app_with_state(state.clone()),
+        &format!("/document/{}/updates", doc_id),
+    )
+    .await;
+    client.next_event("position").await;
+
+    // Saving the included file re-renders the document including it
+    sleep(Duration::from_millis(100)).await;
+    std::fs::write(&snippet_path, "Call `list(all)`\n").unwrap();
+    let data = client.next_event("file_changed").await;
+    assert!(data.contains("list(all)"));
+    assert!(data.contains(r#"{"sourcepos":"1:1-1:7","html":null}"#));
+
+    // So does unsaved content pushed for it
+    let snippet_id = utils::generate_document_id(&snippet_path);
+    state.add_document(snippet_id.clone(), snippet_path.clone());
+    server
+        .put(&format!("/api/document/{}/content", snippet_id))
+        .text(r#"{"content":"Call `remove()`\n"}"#)
+        .await
+        .assert_status_ok();
+    assert!(client.next_event("file_changed").await.contains("remove()"));
+}
+
+#[tokio::test]
+async fn test_unsaved_content_replaces_file_until_reverted() {
+    use tokio::time::{sleep, Duration};
+
+    let file_path = create_temp_file("buffer.md");
+    std::fs::write(&file_path, "# Saved\n").unwrap();
+
+    let state = AppState::new();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path.clone());
+
+    let server = TestServer::new(create_app_with_state(state.clone())).unwrap();
+    let mut client = SseClient::connect(
+        create_app_with_state(state),
+        &format!("/document/{}/updates", doc_id),
+    )
+    .await;
+    client.next_event("position").await;
+
+    let content_url = format!("/api/document/{}/content", doc_id);
+    server
+        .put(&content_url)
+        .text(r##"{"content":"# Unsaved\n"}"##)
+        .await
+        .assert_status_ok();
+    assert!(client.next_event("file_changed").await.contains("Unsaved"));
+    assert!(server
+        .get(&format!("/document/{}", doc_id))
+        .await
+        .text()
+        .contains(">Unsaved</h1>"));
+    let info: DocumentInfo = facet_json::from_str(
+        &server
+            .get(&format!("/api/document/{}", doc_id))
+            .await
+            .text(),
+    )
+    .unwrap();
+    assert!(info.unsaved);
+
+    // Reverting shows the file again
+    server.delete(&content_url).await.assert_status_ok();
+    assert!(client
+        .next_event("file_changed")
+        .await
+        .contains(">Saved</h1>"));
+
+    // Saving the file replaces the unsaved content
+    server
+        .put(&content_url)
+        .text(r##"{"content":"# Typing\n"}"##)
+        .await
+        .assert_status_ok();
+    assert!(client.next_event("file_changed").await.contains("Typing"));
+    sleep(Duration::from_millis(100)).await;
+    std::fs::write(&file_path, "# Written\n").unwrap();
+    assert!(client.next_event("file_changed").await.contains("Written"));
+    let info: DocumentInfo = facet_json::from_str(
+        &server
+            .get(&format!("/api/document/{}", doc_id))
+            .await
+            .text(),
+    )
+    .unwrap();
+    assert!(!info.unsaved);
+
+    server
+        .put(&content_url)
+        .text("{}")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .put("/api/document/nonexistent/content")
+        .text(r#"{"content":""}"#)
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server
+        .delete("/api/document/nonexistent/content")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_websocket_carries_document_events() {
+    let file_path = create_temp_file("socket.md");
+    std::fs::write(&file_path, "# Title\n\nText\n").unwrap();
+
+    let state = AppState::new();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path);
+
+    let server = TestServer::builder()
+        .http_transport()
+        .build(create_app_with_state(state.clone()))
+        .unwrap();
+    let mut socket = server
+        .get_websocket(&format!("/document/{}/ws", doc_id))
+        .await
+        .into_websocket()
+        .await;
+    socket
+        .assert_receive_text(
+            r#"{"event":"position","data":{"sourcepos":"1:1-1:7","cursor":"1:1-1:1","selection":null,"highlight":["1:1-1:7"]}}"#,
+        )
+        .await;
+
+    // Positions and content sent over the socket are broadcast back
+    socket
+        .send_text(r#"{"event":"position","sourcepos":"3:2-3:2"}"#)
+        .await;
+    socket
+        .assert_receive_text(
+            r#"{"event":"position","data":{"sourcepos":"3:1-3:4","cursor":"3:2-3:2","selection":null,"highlight":["3:1-3:4"]}}"#,
+        )
+        .await;
+    assert_eq!(
+        state.get_position(&doc_id).unwrap().cursor.to_string(),
+        "3:2-3:2"
+    );
+
+    socket
+        .send_text(r##"{"event":"content","content":"# Title\n\nEdited\n"}"##)
+        .await;
+    let message = socket.receive_text().await;
+    assert!(message.starts_with(r#"{"event":"file_changed","data":{"blocks":"#));
+    assert!(message.contains(">Edited</p>"));
+
+    socket.send_text(r#"{"event":"revert"}"#).await;
+    socket
+        .assert_receive_text_contains(r#"<p data-sourcepos=\"3:1-3:4\">Text</p>"#)
+        .await;
+
+    // Requests arriving while a change renders do not drop its message
+    socket
+        .send_text(r##"{"event":"content","content":"# Title\n\nTyped\n"}"##)
+        .await;
+    socket
+        .send_text(r#"{"event":"position","sourcepos":"1:2-1:2"}"#)
+        .await;
+    socket.assert_receive_text_contains(">Typed</p>").await;
+    socket
+        .assert_receive_text_contains(r#""cursor":"1:2-1:2""#)
+        .await;
+
+    // Unlike previews, editors are told where previews scroll to
+    state.report_preview_scroll(&doc_id, "1:1-1:7".parse().unwrap());
+    socket
+        .assert_receive_text(r#"{"event":"scroll","data":{"sourcepos":"1:1-1:7"}}"#)
+        .await;
+
+    socket.send_text(r#"{"event":"save"}"#).await;
+    socket
+        .assert_receive_text(r#"{"event":"error","data":{"error":"Unknown event: save"}}"#)
+        .await;
+    socket
+        .send_text(r#"{"event":"position","sourcepos":"0:0-0:0"}"#)
+        .await;
+    socket
+        .assert_receive_text_contains(r#""error":"invalid sourcepos"#)
+        .await;
+
+    let info = state.get_document_info(&doc_id).unwrap();
+    assert_eq!(info.subscriber_count, 1);
+
+    server
+        .get("/document/nonexistent/ws")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_reconnecting_client_is_sent_missed_render() {
+    let file_path = create_temp_file("resume.md");
+    std::fs::write(&file_path, "# Title\n\nText\n").unwrap();
+
+    let state = AppState::new();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path);
+    let updates_path = format!("/document/{}/updates", doc_id);
+
+    let mut client = SseClient::connect(create_app_with_state(state.clone()), &updates_path).await;
+    client.next_event("position").await;
+    assert_eq!(client.last_event_id.as_deref(), Some("0"));
+    state.set_unsaved_content(&doc_id, "# Title\n\nEdited\n".to_string());
+    client.next_event("file_changed").await;
+    assert_eq!(client.last_event_id.as_deref(), Some("1"));
+    drop(client);
+
+    // Changes while nobody is connected still advance the ids
+    state.set_unsaved_content(&doc_id, "# Title\n\nMissed\n".to_string());
+
+    let mut client = SseClient::connect_with_headers(
+        create_app_with_state(state.clone()),
+        &updates_path,
+        "Last-Event-ID: 1\r\n",
+    )
+    .await;
+    let data = client.next_event("file_changed").await;
+    assert!(data.contains(r#"{"sourcepos":"1:1-1:7","html":"<h1"#));
+    assert!(data.contains(">Missed</p>"));
+    client.next_event("position").await;
+    assert_eq!(client.last_event_id.as_deref(), Some("2"));
+
+    // Clients that are up to date only get the position
+    let mut client = SseClient::connect_with_headers(
+        create_app_with_state(state.clone()),
+        &updates_path,
+        "Last-Event-ID: 2\r\n",
+    )
+    .await;
+    client.next_event("position").await;
+    assert_eq!(client.received, vec!["position"]);
+
+    // Ids from before a server restart are not trusted
+    let mut client = SseClient::connect_with_headers(
+        create_app_with_state(state),
+        &updates_path,
+        "Last-Event-ID: 42\r\n",
+    )
+    .await;
+    client.next_event("position").await;
+    assert_eq!(client.received, vec!["file_changed", "position"]);
+}
+
+#[tokio::test]
+async fn test_watcher_reports_replaced_renamed_and_deleted_files() {
+    use tokio::time::{sleep, Duration};
+
+    let file_path = create_temp_file("atomic.md");
+    std::fs::write(&file_path, "# Original\n").unwrap();
+    let state = AppState::new();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path.clone());
+
+    let mut client = SseClient::connect(
+        create_app_with_state(state.clone()),
+        &format!("/document/{}/updates", doc_id),
+    )
+    .await;
+    client.next_event("position").await;
+    sleep(Duration::from_millis(100)).await;
+
+    // Saving by writing a temporary file and renaming it over the document
+    let temp_path = format!("{}.tmp", file_path);
+    std::fs::write(&temp_path, "# Replaced\n").unwrap();
+    std::fs::rename(&temp_path, &file_path).unwrap();
+    assert!(client.next_event("file_changed").await.contains("Replaced"));
+
+    // Later saves are still seen
+    sleep(Duration::from_millis(100)).await;
+    std::fs::write(&file_path, "# Saved again\n").unwrap();
+    assert!(client
+        .next_event("file_changed")
+        .await
+        .contains("Saved again"));
+
+    let renamed_path = file_path.replace("atomic.md", "renamed.md");
+    std::fs::rename(&file_path, &renamed_path).unwrap();
+    assert_eq!(
+        client.next_event("file_renamed").await,
+        format!(r#"{{"filepath":"{}","title":"renamed"}}"#, renamed_path)
+    );
+    assert_eq!(
+        state.get_filepath_by_id(&doc_id),
+        Some(renamed_path.clone())
+    );
+
+    std::fs::remove_file(&renamed_path).unwrap();
+    assert_eq!(
+        client.next_event("file_deleted").await,
+        format!(r#"{{"filepath":"{}","title":"renamed"}}"#, renamed_path)
+    );
+
+    // The document comes back with its file
+    std::fs::write(&renamed_path, "# Restored\n").unwrap();
+    assert!(client.next_event("file_changed").await.contains("Restored"));
+}
+
+#[tokio::test]
+async fn test_position_resolves_to_nearest_block() {
+    let file_path = create_temp_file("cursor.md");
+    std::fs::write(
+        &file_path,
+        "# Title\n\nFirst *emph* paragraph\n\n- item one\n- item two\n",
+    )
+    .unwrap();
+    let state = AppState::new();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path);
+
+    let mut client = SseClient::connect(
+        create_app_with_state(state.clone()),
+        &format!("/document/{}/updates", doc_id),
+    )
+    .await;
+    // The default position resolves to the heading
+    assert_eq!(
+        client.next_event("position").await,
+        r#"{"sourcepos":"1:1-1:7","cursor":"1:1-1:1","selection":null,"highlight":["1:1-1:7"]}"#
+    );
+
+    let server = TestServer::new(create_app_with_state(state.clone())).unwrap();
+    // The whole top-level block the target is in is highlighted
+    for (cursor, resolved, highlight) in [
+        ("3:7-3:7", "3:1-3:22", "3:1-3:22"),
+        // Inline spans are not targets of their own
+        ("3:9-3:9", "3:1-3:22", "3:1-3:22"),
+        ("6:4-6:4", "6:1-6:10", "5:1-6:10"),
+        ("2:1-2:1", "1:1-1:7", "1:1-1:7"),
+        ("42:7-42:7", "6:1-6:10", "5:1-6:10"),
+    ] {
+        server
+            .post(&format!("/api/document/{}/position", doc_id))
+            .text(format!(r#"{{"sourcepos":"{}"}}"#, cursor))
+            .await
+            .assert_status(StatusCode::CREATED);
+        assert_eq!(
+            client.next_event("position").await,
+            format!(
+                r#"{{"sourcepos":"{0}","cursor":"{1}","selection":null,"highlight":["{2}"]}}"#,
+                resolved, cursor, highlight
+            )
+        );
+    }
+
+    // The editor's own position is kept for the JSON API
+    assert_eq!(
+        state.get_position(&doc_id).map(|position| position.cursor),
+        "42:7-42:7".parse().ok()
+    );
+}
+
+#[tokio::test]
+async fn test_selection_highlights_touched_blocks() {
+    let file_path = create_temp_file("selection.md");
+    std::fs::write(
+        &file_path,
+        "# Title\n\nFirst paragraph\n\n- item one\n- item two\n\nLast\n",
+    )
+    .unwrap();
+    let state = AppState::new();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path);
+
+    let mut client = SseClient::connect(
+        create_app_with_state(state.clone()),
+        &format!("/document/{}/updates", doc_id),
+    )
+    .await;
+    client.next_event("position").await;
+
+    let server = TestServer::new(create_app_with_state(state.clone())).unwrap();
+    server
+        .post(&format!("/api/document/{}/position", doc_id))
+        .text(r#"{"sourcepos":"5:3-5:3","selection":"3:6-5:3"}"#)
+        .await
+        .assert_status(StatusCode::CREATED);
+    assert_eq!(
+        client.next_event("position").await,
+        r#"{"sourcepos":"5:1-5:10","cursor":"5:3-5:3","selection":"3:6-5:3","highlight":["3:1-3:15","5:1-7:0"]}"#
+    );
+
+    let response = server.get(&format!("/api/document/{}", doc_id)).await;
+    let info: DocumentInfo = facet_json::from_str(&response.text()).unwrap();
+    assert_eq!(info.sourcepos, "5:3-5:3");
+    assert_eq!(info.selection.as_deref(), Some("3:6-5:3"));
+
+    server
+        .post(&format!("/api/document/{}/position", doc_id))
+        .text(r#"{"sourcepos":"5:3-5:3","selection":"5:3"}"#)
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_preview_scroll_is_sent_to_editors() {
+    let file_path = create_temp_file("scroll.md");
+    let state = AppState::new();
+    let doc_id = utils::generate_document_id(&file_path);
+    state.add_document(doc_id.clone(), file_path);
+
+    let mut editor = SseClient::connect(
+        create_app_with_state(state.clone()),
+        &format!("/api/document/{}/events", doc_id),
+    )
+    .await;
+    editor.wait_until_connected().await;
+
+    let server = TestServer::new(create_app_with_state(state.clone())).unwrap();
+    server
+        .post(&format!("/api/document/{}/scroll", doc_id))
+        .text(r#"{"sourcepos":"12:1-14:20"}"#)
+        .await
+        .assert_status(StatusCode::CREATED);
+
+    let data = editor.next_event("scroll").await;
+    assert_eq!(data, r#"{"sourcepos":"12:1-14:20"}"#);
+
+    // Scrolling the preview does not move the editor's recorded position
+    assert_eq!(state.get_position(&doc_id), Some(EditorPosition::START));
+
+    server
+        .post("/api/document/nonexistent/scroll")
+        .text(r#"{"sourcepos":"1:1-1:1"}"#)
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server
+        .get("/api/document/nonexistent/events")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_document_json_api() {
+    let state = AppState::new();
+    let server = TestServer::new(create_app_with_state(state.clone())).unwrap();
+
+    let response = server.get("/api/documents").await;
+    response.assert_status_ok();
+    response.assert_header("content-type", "application/json");
+    response.assert_text(r#"{"documents":[]}"#);
+
+    // Register an existing file and one that cannot be read
+    let example_path = std::env::current_dir().unwrap().join("examples/simple.md");
+    let create_request = CreateDocumentRequest {
+        filepath: example_path.to_str().unwrap().to_string(),
+        extensions: None,
+    };
+    let create_response = server
+        .post("/api/document")
+        .text(facet_json::to_string(&create_request))
+        .await;
+    let doc_id = facet_json::from_str::<CreateDocumentResponse>(&create_response.text())
+        .unwrap()
+        .id;
+
+    let missing_id = utils::generate_document_id("/path/to/missing.md");
+    state.add_document(missing_id.clone(), "/path/to/missing.md".to_string());
+    server
+        .get(&format!("/document/{}", missing_id))
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+
+    let response = server.get(&format!("/api/document/{}", doc_id)).await;
+    response.assert_status_ok();
+    response.assert_header("content-type", "application/json");
+    let info: DocumentInfo = facet_json::from_str(&response.text()).unwrap();
+    assert_eq!(info.id, doc_id);
+    assert_eq!(
+        info.filepath,
+        utils::to_absolute_path(example_path.to_str().unwrap())
+    );
+    assert_eq!(info.title, "simple");
+    assert!(info.last_modified.is_some());
+    assert_eq!(info.sourcepos, "1:1-1:1");
+    assert_eq!(info.subscriber_count, 0);
+    assert_eq!(info.last_error, None);
+
+    let response = server.get(&format!("/api/document/{}", missing_id)).await;
+    let info: DocumentInfo = facet_json::from_str(&response.text()).unwrap();
+    assert_eq!(info.last_modified, None);
+    assert!(info.last_error.unwrap().contains("/path/to/missing.md"));
+
+    // Open SSE connections are counted as subscribers
+    let mut client = SseClient::connect(
+        create_app_with_state(state.clone()),
+        &format!("/document/{}/updates", doc_id),
+    )
+    .await;
+    client.next_event("position").await;
+
+    let response = server.get("/api/documents").await;
+    let list: DocumentListResponse = facet_json::from_str(&response.text()).unwrap();
+    assert_eq!(list.documents.len(), 2);
+    let listed = list.documents.iter().find(|doc| doc.id == doc_id).unwrap();
+    assert_eq!(listed.subscriber_count, 1);
+
+    drop(client);
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let info = state.get_document_info(&doc_id).unwrap();
+    assert_eq!(info.subscriber_count, 0);
+
+    server
+        .get("/api/document/nonexistent-id")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}